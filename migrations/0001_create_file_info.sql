@@ -0,0 +1,9 @@
+-- 文件信息
+CREATE TABLE IF NOT EXISTS file_info (
+    id INTEGER PRIMARY KEY,
+    hash_key TEXT NOT NULL,
+    total_frame INTEGER NOT NULL,
+    file_path TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    UNIQUE(hash_key)
+);
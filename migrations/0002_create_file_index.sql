@@ -0,0 +1,12 @@
+-- 本地搜索索引
+CREATE TABLE IF NOT EXISTS file_index (
+    id INTEGER PRIMARY KEY,
+    file_path TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    ext TEXT NOT NULL,
+    dir_path TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    date_created INTEGER NOT NULL,
+    scanned_at INTEGER NOT NULL,
+    UNIQUE(file_path)
+);
@@ -0,0 +1,25 @@
+-- 视频容器信息(ffprobe format)
+CREATE TABLE IF NOT EXISTS media_info (
+    file_id INTEGER PRIMARY KEY REFERENCES file_info(id) ON DELETE CASCADE,
+    duration REAL,
+    container TEXT NOT NULL,
+    bit_rate INTEGER
+);
+
+-- 视频/音频/字幕流信息(ffprobe streams)
+CREATE TABLE IF NOT EXISTS media_stream (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES file_info(id) ON DELETE CASCADE,
+    stream_index INTEGER NOT NULL,
+    codec_type TEXT NOT NULL,
+    codec_name TEXT,
+    width INTEGER,
+    height INTEGER,
+    frame_rate REAL,
+    pix_fmt TEXT,
+    channels INTEGER,
+    nb_frames INTEGER,
+    language TEXT,
+    title TEXT,
+    UNIQUE(file_id, stream_index)
+);
@@ -0,0 +1,17 @@
+-- 缩略图生成任务队列
+CREATE TABLE IF NOT EXISTS thumbnail_job (
+    id INTEGER PRIMARY KEY,
+    hash_key TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    -- queued|running|done|failed
+    state TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    -- 下次可执行时间(毫秒时间戳), 用于失败重试退避
+    next_run_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    UNIQUE(hash_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_thumbnail_job_state ON thumbnail_job(state, next_run_at);
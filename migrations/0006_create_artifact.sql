@@ -0,0 +1,10 @@
+-- 生成的缩略图等产物, 按文件hash_key存储
+CREATE TABLE IF NOT EXISTS artifact (
+    id INTEGER PRIMARY KEY,
+    hash_key TEXT NOT NULL,
+    -- keyframes|gif
+    kind TEXT NOT NULL,
+    path TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER) * 1000),
+    UNIQUE(hash_key, kind)
+);
@@ -0,0 +1,10 @@
+-- 关键帧图片对应的视频时间点
+CREATE TABLE IF NOT EXISTS keyframe (
+    id INTEGER PRIMARY KEY,
+    hash_key TEXT NOT NULL,
+    -- 图片文件名, 如: 0001.png
+    name TEXT NOT NULL,
+    -- 显示时间戳(秒)
+    pts_time REAL NOT NULL,
+    UNIQUE(hash_key, name)
+);
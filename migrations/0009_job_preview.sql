@@ -0,0 +1,2 @@
+-- 任务需要生成的预览方式: gif|clip|both, 已有任务保持之前的行为(都生成)
+ALTER TABLE thumbnail_job ADD COLUMN preview TEXT NOT NULL DEFAULT 'both';
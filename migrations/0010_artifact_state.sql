@@ -0,0 +1,11 @@
+-- 产物生成状态: 未完成、失败或生成参数变化时重新生成
+-- running|done|failed, 已有记录视为已完成
+ALTER TABLE artifact ADD COLUMN state TEXT NOT NULL DEFAULT 'done';
+-- 生成参数, 已有记录为NULL(参数未知, 下次执行任务时重新生成)
+ALTER TABLE artifact ADD COLUMN params TEXT;
+-- 帧数(关键帧/雪碧图/联系表的图片数, 短视频的片段数)
+ALTER TABLE artifact ADD COLUMN frame_count INTEGER;
+ALTER TABLE artifact ADD COLUMN error TEXT;
+-- 完成时间(毫秒时间戳)
+ALTER TABLE artifact ADD COLUMN completed_at INTEGER;
+UPDATE artifact SET completed_at = created_at;
@@ -1,10 +1,15 @@
-use crate::{fhash, model, thumbnail};
+use crate::{fhash, model, probe};
 
-use crate::model::FileInfo;
+use crate::model::{
+    Artifact, ArtifactKind, FileIndex, FileInfo, JobState, Keyframe, MediaInfo, MediaStream,
+    PreviewMode, ThumbnailJob,
+};
+use crate::search::{SearchRequest, SortOrder};
 use anyhow::Result;
 use dotenvy::var;
 use sqlx::sqlite::SqlitePoolOptions;
-use sqlx::SqlitePool;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use std::collections::HashMap;
 use tracing::info;
 
 pub async fn connect_pool() -> Result<SqlitePool> {
@@ -19,21 +24,45 @@ pub async fn connect_pool() -> Result<SqlitePool> {
         .connect(&format!("sqlite:{}", db_path))
         .await
         .expect("连接数据库失败");
-    sqlx::query(
-        r#"CREATE TABLE IF NOT EXISTS file_info (
-                id INTEGER PRIMARY KEY,
-                hash_key TEXT NOT NULL,
-                total_frame INTEGER NOT NULL,
-                file_path TEXT NOT NULL,
-                file_size INTEGER NOT NULL,
-                UNIQUE(hash_key)
-            )"#,
-    )
-    .execute(&pool)
-    .await?;
     Ok(pool)
 }
 
+/// 连接内存数据库并执行所有迁移(测试用), 只使用一个连接, 连接关闭时数据丢失
+#[cfg(test)]
+pub(crate) async fn connect_memory_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("连接内存数据库失败");
+    crate::migrate::run(&pool).await.expect("执行迁移失败");
+    pool
+}
+
+/// 旧版本以32位保存文件大小, 超过4GB的文件大小被截断, 根据磁盘上的文件重新计算
+pub async fn recompute_file_sizes(pool: &SqlitePool) -> Result<()> {
+    let rows = sqlx::query_as::<_, (i64, String)>("SELECT id, file_path FROM file_info")
+        .fetch_all(pool)
+        .await?;
+    let mut updated = 0;
+    for (id, file_path) in rows {
+        // 文件已不存在时保留原值
+        let Ok(metadata) = std::fs::metadata(&file_path) else {
+            continue;
+        };
+        sqlx::query("UPDATE file_info SET file_size = ? WHERE id = ?")
+            .bind(metadata.len() as i64)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        updated += 1;
+    }
+    info!("重新计算文件大小: {}条记录", updated);
+    Ok(())
+}
+
 /// 根据文件hash值查询文件信息
 pub async fn query_by_hash_key(
     pool: &SqlitePool,
@@ -48,6 +77,47 @@ pub async fn query_by_hash_key(
     Ok(file_info)
 }
 
+/// 根据文件id查询文件信息
+pub async fn query_by_id(pool: &SqlitePool, id: u32) -> Result<Option<model::FileInfo>> {
+    let file_info = sqlx::query_as::<_, model::FileInfo>(
+        "SELECT id, hash_key, total_frame, file_path, file_size FROM file_info WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(file_info)
+}
+
+/// 查询所有已入库的文件信息
+pub async fn query_all_file_info(pool: &SqlitePool) -> Result<Vec<FileInfo>> {
+    let list = sqlx::query_as::<_, FileInfo>(
+        "SELECT id, hash_key, total_frame, file_path, file_size FROM file_info ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(list)
+}
+
+/// 根据文件路径批量查询已入库的文件信息
+pub async fn query_by_file_paths(
+    pool: &SqlitePool,
+    file_paths: &[String],
+) -> Result<Vec<FileInfo>> {
+    if file_paths.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT id, hash_key, total_frame, file_path, file_size FROM file_info WHERE file_path IN (",
+    );
+    let mut separated = qb.separated(", ");
+    for file_path in file_paths {
+        separated.push_bind(file_path);
+    }
+    separated.push_unseparated(")");
+    let list = qb.build_query_as::<FileInfo>().fetch_all(pool).await?;
+    Ok(list)
+}
+
 pub async fn query_by_file_path(
     pool: &SqlitePool,
     file_path: &str,
@@ -55,7 +125,7 @@ pub async fn query_by_file_path(
     // 计算文件hash值dd
     let hash_key = fhash::compute_sample_hash(file_path)?;
     info!("文件路径: {}, hash_key: {}", file_path, hash_key);
-    Ok(query_by_hash_key(pool, hash_key).await?)
+    query_by_hash_key(pool, hash_key).await
 }
 
 /// 查询文件信息，如果不存在则插入新记录
@@ -70,24 +140,596 @@ pub async fn query_and_update_by_file_path(
     create_file_info(pool, file_path).await
 }
 
+/// 获取视频信息并插入新记录(不生成缩略图)
 pub async fn create_file_info(pool: &SqlitePool, file_path: &str) -> Result<FileInfo> {
+    // 获取视频信息
+    let probe = probe::probe(file_path).await?;
     // 如果没有记录，则插入新记录
-    let new_file_info = model::FileInfo::new(
+    let mut new_file_info = model::FileInfo::new(
         0,
         fhash::compute_sample_hash(file_path)?,
-        // 获取视频总帧数
-        thumbnail::obtain_total_frame_count(file_path).await?,
+        // 视频总帧数, 无法获取时为0
+        probe.total_frames().unwrap_or(0),
         file_path.to_string(),
-        std::fs::metadata(file_path)?.len() as u32,
+        std::fs::metadata(file_path)?.len(),
     );
-    sqlx::query(
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
         "INSERT INTO file_info (hash_key, total_frame, file_path, file_size) VALUES (?, ?, ?, ?)",
     )
     .bind(&new_file_info.hash_key)
     .bind(new_file_info.total_frame)
     .bind(&new_file_info.file_path)
-    .bind(new_file_info.file_size)
-    .execute(pool)
+    .bind(new_file_info.file_size as i64)
+    .execute(&mut *tx)
     .await?;
+    new_file_info.id = result.last_insert_rowid() as u32;
+    insert_media_info(&mut tx, new_file_info.id, &probe).await?;
+    tx.commit().await?;
     Ok(new_file_info)
 }
+
+/// 保存视频信息
+async fn insert_media_info(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    file_id: u32,
+    probe: &probe::ProbeResult,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO media_info (file_id, duration, container, bit_rate) VALUES (?, ?, ?, ?)",
+    )
+    .bind(file_id)
+    .bind(probe.info.duration)
+    .bind(&probe.info.container)
+    .bind(probe.info.bit_rate)
+    .execute(&mut **tx)
+    .await?;
+    for stream in &probe.streams {
+        sqlx::query(
+            r#"INSERT OR REPLACE INTO media_stream
+                (file_id, stream_index, codec_type, codec_name, width, height, frame_rate, pix_fmt, channels, nb_frames, language, title)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+        )
+        .bind(file_id)
+        .bind(stream.stream_index)
+        .bind(&stream.codec_type)
+        .bind(&stream.codec_name)
+        .bind(stream.width)
+        .bind(stream.height)
+        .bind(stream.frame_rate)
+        .bind(&stream.pix_fmt)
+        .bind(stream.channels)
+        .bind(stream.nb_frames)
+        .bind(&stream.language)
+        .bind(&stream.title)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// 查询视频容器信息
+pub async fn query_media_info(pool: &SqlitePool, file_id: u32) -> Result<Option<MediaInfo>> {
+    let info = sqlx::query_as::<_, MediaInfo>(
+        "SELECT file_id, duration, container, bit_rate FROM media_info WHERE file_id = ?",
+    )
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(info)
+}
+
+/// 查询视频的所有流信息
+pub async fn query_media_streams(pool: &SqlitePool, file_id: u32) -> Result<Vec<MediaStream>> {
+    let streams = sqlx::query_as::<_, MediaStream>(
+        r#"SELECT id, file_id, stream_index, codec_type, codec_name, width, height, frame_rate, pix_fmt, channels, nb_frames, language, title
+            FROM media_stream WHERE file_id = ? ORDER BY stream_index"#,
+    )
+    .bind(file_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(streams)
+}
+
+/// 查询本地索引中所有文件的大小和创建时间, 扫描时用于跳过未变化的文件
+pub async fn query_file_index_stamps(pool: &SqlitePool) -> Result<HashMap<String, (i64, i64)>> {
+    let rows = sqlx::query_as::<_, (String, i64, i64)>(
+        "SELECT file_path, file_size, date_created FROM file_index",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(file_path, file_size, date_created)| (file_path, (file_size, date_created)))
+        .collect())
+}
+
+/// 批量插入或更新本地索引记录, 在同一个事务中提交
+pub async fn upsert_file_indexes(pool: &SqlitePool, file_indexes: &[FileIndex]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for file_index in file_indexes {
+        sqlx::query(
+            r#"INSERT INTO file_index (file_path, file_name, ext, dir_path, file_size, date_created, scanned_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(file_path) DO UPDATE SET
+                    file_size = excluded.file_size,
+                    date_created = excluded.date_created,
+                    scanned_at = excluded.scanned_at"#,
+        )
+        .bind(&file_index.file_path)
+        .bind(&file_index.file_name)
+        .bind(&file_index.ext)
+        .bind(&file_index.dir_path)
+        .bind(file_index.file_size)
+        .bind(file_index.date_created)
+        .bind(file_index.scanned_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 批量删除本地索引记录(如扫描中未出现的文件), 在同一个事务中提交
+pub async fn delete_file_indexes(pool: &SqlitePool, file_paths: &[String]) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let mut removed = 0;
+    for file_path in file_paths {
+        let result = sqlx::query("DELETE FROM file_index WHERE file_path = ?")
+            .bind(file_path)
+            .execute(&mut *tx)
+            .await?;
+        removed += result.rows_affected();
+    }
+    tx.commit().await?;
+    Ok(removed)
+}
+
+/// 按搜索条件查询本地索引(分页): 每个关键字都需出现在文件名中
+pub async fn search_file_index(pool: &SqlitePool, req: &SearchRequest) -> Result<Vec<FileIndex>> {
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT id, file_path, file_name, ext, dir_path, file_size, date_created, scanned_at FROM file_index",
+    );
+    push_file_index_filters(&mut qb, req);
+    qb.push(" ORDER BY ")
+        .push(order_by_clause(req.sort))
+        .push(" LIMIT ")
+        .push_bind(req.max_results)
+        .push(" OFFSET ")
+        .push_bind(req.offset);
+    let list = qb.build_query_as::<FileIndex>().fetch_all(pool).await?;
+    Ok(list)
+}
+
+/// 按搜索条件统计本地索引中匹配的总数
+pub async fn count_file_index(pool: &SqlitePool, req: &SearchRequest) -> Result<u32> {
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT COUNT(*) FROM file_index");
+    push_file_index_filters(&mut qb, req);
+    let (count,) = qb.build_query_as::<(u32,)>().fetch_one(pool).await?;
+    Ok(count)
+}
+
+// 拼接搜索条件对应的WHERE子句
+fn push_file_index_filters(qb: &mut QueryBuilder<Sqlite>, req: &SearchRequest) {
+    qb.push(" WHERE 1 = 1");
+    if let Some(min) = req.min_size {
+        qb.push(" AND file_size > ").push_bind(min as i64);
+    }
+    if let Some(max) = req.max_size {
+        qb.push(" AND file_size < ").push_bind(max as i64);
+    }
+    if !req.extensions.is_empty() {
+        qb.push(" AND (");
+        for (i, ext) in req.extensions.iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push("file_name LIKE ")
+                .push_bind(format!("%.{}", escape_like(ext)))
+                .push(" ESCAPE '\\'");
+        }
+        qb.push(")");
+    }
+    if !req.include_paths.is_empty() {
+        qb.push(" AND (");
+        for (i, path) in req.include_paths.iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push("file_path LIKE ")
+                .push_bind(format!("{}%", escape_like(path)))
+                .push(" ESCAPE '\\'");
+        }
+        qb.push(")");
+    }
+    for path in &req.exclude_paths {
+        qb.push(" AND file_path NOT LIKE ")
+            .push_bind(format!("{}%", escape_like(path)))
+            .push(" ESCAPE '\\'");
+    }
+    for term in req.terms() {
+        if req.match_case {
+            // instr区分大小写
+            qb.push(" AND instr(file_name, ")
+                .push_bind(term.to_string())
+                .push(") > 0");
+        } else {
+            qb.push(" AND file_name LIKE ")
+                .push_bind(format!("%{}%", escape_like(term)))
+                .push(" ESCAPE '\\'");
+        }
+    }
+}
+
+// 排序方式对应的ORDER BY子句
+fn order_by_clause(sort: SortOrder) -> &'static str {
+    match sort {
+        SortOrder::NameAsc => "file_name COLLATE NOCASE ASC",
+        SortOrder::NameDesc => "file_name COLLATE NOCASE DESC",
+        SortOrder::PathAsc => "file_path COLLATE NOCASE ASC",
+        SortOrder::PathDesc => "file_path COLLATE NOCASE DESC",
+        SortOrder::SizeAsc => "file_size ASC",
+        SortOrder::SizeDesc => "file_size DESC",
+        SortOrder::DateCreatedAsc => "date_created ASC",
+        SortOrder::DateCreatedDesc => "date_created DESC",
+    }
+}
+
+// 转义LIKE中的通配符
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+const JOB_COLUMNS: &str =
+    "id, hash_key, file_path, state, preview, attempts, last_error, next_run_at, created_at, updated_at";
+
+/// 加入缩略图任务, 同一hash_key只保留一个任务:
+/// 已在排队或执行中的任务保持不变(预览方式合并), 已结束的任务按新的预览方式重新排队
+pub async fn enqueue_job(
+    pool: &SqlitePool,
+    hash_key: &str,
+    file_path: &str,
+    preview: PreviewMode,
+    now: i64,
+) -> Result<ThumbnailJob> {
+    let job = sqlx::query_as::<_, ThumbnailJob>(&format!(
+        r#"INSERT INTO thumbnail_job (hash_key, file_path, state, preview, attempts, next_run_at, created_at, updated_at)
+            VALUES (?, ?, 'queued', ?, 0, ?, ?, ?)
+            ON CONFLICT(hash_key) DO UPDATE SET
+                file_path = excluded.file_path,
+                preview = CASE WHEN state IN ('done', 'failed', 'cancelled') OR preview = excluded.preview
+                    THEN excluded.preview ELSE 'both' END,
+                attempts = CASE WHEN state IN ('done', 'failed', 'cancelled') THEN 0 ELSE attempts END,
+                last_error = CASE WHEN state IN ('done', 'failed', 'cancelled') THEN NULL ELSE last_error END,
+                next_run_at = CASE WHEN state IN ('done', 'failed', 'cancelled') THEN excluded.next_run_at ELSE next_run_at END,
+                updated_at = CASE WHEN state IN ('done', 'failed', 'cancelled') THEN excluded.updated_at ELSE updated_at END,
+                state = CASE WHEN state IN ('done', 'failed', 'cancelled') THEN 'queued' ELSE state END
+            RETURNING {}"#,
+        JOB_COLUMNS
+    ))
+    .bind(hash_key)
+    .bind(file_path)
+    .bind(preview)
+    .bind(now)
+    .bind(now)
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+    Ok(job)
+}
+
+/// 领取一个可执行的任务并标记为执行中
+pub async fn claim_next_job(pool: &SqlitePool, now: i64) -> Result<Option<ThumbnailJob>> {
+    let job = sqlx::query_as::<_, ThumbnailJob>(&format!(
+        r#"UPDATE thumbnail_job SET state = 'running', attempts = attempts + 1, updated_at = ?
+            WHERE id = (
+                SELECT id FROM thumbnail_job WHERE state = 'queued' AND next_run_at <= ?
+                ORDER BY next_run_at, id LIMIT 1
+            )
+            RETURNING {}"#,
+        JOB_COLUMNS
+    ))
+    .bind(now)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+    Ok(job)
+}
+
+/// 标记任务完成, preview为本次执行的预览方式; 执行期间加入任务合并了新的预览方式时重新排队,
+/// 返回任务是否已完成
+pub async fn finish_job(
+    pool: &SqlitePool,
+    id: i64,
+    preview: PreviewMode,
+    now: i64,
+) -> Result<bool> {
+    let (state,) = sqlx::query_as::<_, (JobState,)>(
+        r#"UPDATE thumbnail_job SET
+                state = CASE WHEN preview = ? THEN 'done' ELSE 'queued' END,
+                attempts = CASE WHEN preview = ? THEN attempts ELSE 0 END,
+                next_run_at = CASE WHEN preview = ? THEN next_run_at ELSE ? END,
+                last_error = NULL,
+                updated_at = ?
+            WHERE id = ?
+            RETURNING state"#,
+    )
+    .bind(preview)
+    .bind(preview)
+    .bind(preview)
+    .bind(now)
+    .bind(now)
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+    Ok(state == JobState::Done)
+}
+
+/// 任务执行失败: next_run_at为Some时重新排队等待重试, 否则标记为失败
+pub async fn fail_job(
+    pool: &SqlitePool,
+    id: i64,
+    error: &str,
+    next_run_at: Option<i64>,
+    now: i64,
+) -> Result<()> {
+    let state = if next_run_at.is_some() {
+        "queued"
+    } else {
+        "failed"
+    };
+    sqlx::query(
+        "UPDATE thumbnail_job SET state = ?, last_error = ?, next_run_at = COALESCE(?, next_run_at), updated_at = ? WHERE id = ?",
+    )
+    .bind(state)
+    .bind(error)
+    .bind(next_run_at)
+    .bind(now)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 取消处于指定状态的任务, 状态已变化(如排队中的任务已被领取)时返回false
+pub async fn cancel_job(
+    pool: &SqlitePool,
+    hash_key: &str,
+    state: JobState,
+    error: &str,
+    now: i64,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE thumbnail_job SET state = 'cancelled', last_error = ?, updated_at = ? WHERE hash_key = ? AND state = ?",
+    )
+    .bind(error)
+    .bind(now)
+    .bind(hash_key)
+    .bind(state)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 中断的任务重新排队, 本次执行不计入执行次数
+pub async fn requeue_job(pool: &SqlitePool, id: i64, now: i64) -> Result<()> {
+    sqlx::query(
+        "UPDATE thumbnail_job SET state = 'queued', attempts = MAX(attempts - 1, 0), next_run_at = ?, updated_at = ? WHERE id = ? AND state = 'running'",
+    )
+    .bind(now)
+    .bind(now)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 服务异常退出时遗留的执行中任务重新排队
+pub async fn requeue_running_jobs(pool: &SqlitePool, now: i64) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE thumbnail_job SET state = 'queued', next_run_at = ?, updated_at = ? WHERE state = 'running'",
+    )
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// 根据文件hash值查询缩略图任务
+pub async fn query_job_by_hash_key(
+    pool: &SqlitePool,
+    hash_key: &str,
+) -> Result<Option<ThumbnailJob>> {
+    let job = sqlx::query_as::<_, ThumbnailJob>(&format!(
+        "SELECT {} FROM thumbnail_job WHERE hash_key = ?",
+        JOB_COLUMNS
+    ))
+    .bind(hash_key)
+    .fetch_optional(pool)
+    .await?;
+    Ok(job)
+}
+
+/// 记录已有产物的路径(用于迁移旧版本数据, 不记录生成参数)
+pub async fn upsert_artifact(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+    path: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"INSERT INTO artifact (hash_key, kind, path) VALUES (?, ?, ?)
+            ON CONFLICT(hash_key, kind) DO UPDATE SET path = excluded.path"#,
+    )
+    .bind(hash_key)
+    .bind(kind)
+    .bind(path)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+const ARTIFACT_COLUMNS: &str =
+    "id, hash_key, kind, path, created_at, state, params, frame_count, error, completed_at";
+
+/// 查询文件的所有生成产物
+pub async fn query_artifacts(pool: &SqlitePool, hash_key: &str) -> Result<Vec<Artifact>> {
+    let list = sqlx::query_as::<_, Artifact>(&format!(
+        "SELECT {} FROM artifact WHERE hash_key = ? ORDER BY id",
+        ARTIFACT_COLUMNS
+    ))
+    .bind(hash_key)
+    .fetch_all(pool)
+    .await?;
+    Ok(list)
+}
+
+/// 查询文件的指定类型产物
+pub async fn query_artifact(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+) -> Result<Option<Artifact>> {
+    let artifact = sqlx::query_as::<_, Artifact>(&format!(
+        "SELECT {} FROM artifact WHERE hash_key = ? AND kind = ?",
+        ARTIFACT_COLUMNS
+    ))
+    .bind(hash_key)
+    .bind(kind)
+    .fetch_optional(pool)
+    .await?;
+    Ok(artifact)
+}
+
+/// 标记产物开始生成, 记录路径和生成参数
+pub async fn start_artifact(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+    path: &str,
+    params: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"INSERT INTO artifact (hash_key, kind, path, state, params) VALUES (?, ?, ?, 'running', ?)
+            ON CONFLICT(hash_key, kind) DO UPDATE SET
+                path = excluded.path,
+                state = 'running',
+                params = excluded.params,
+                frame_count = NULL,
+                error = NULL,
+                completed_at = NULL"#,
+    )
+    .bind(hash_key)
+    .bind(kind)
+    .bind(path)
+    .bind(params)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 标记产物生成完成
+pub async fn finish_artifact(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+    frame_count: u32,
+) -> Result<()> {
+    sqlx::query(
+        r#"UPDATE artifact SET state = 'done', frame_count = ?,
+                completed_at = CAST(strftime('%s', 'now') AS INTEGER) * 1000
+            WHERE hash_key = ? AND kind = ?"#,
+    )
+    .bind(frame_count)
+    .bind(hash_key)
+    .bind(kind)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 标记产物生成失败
+pub async fn fail_artifact(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+    error: &str,
+) -> Result<()> {
+    sqlx::query("UPDATE artifact SET state = 'failed', error = ? WHERE hash_key = ? AND kind = ?")
+        .bind(error)
+        .bind(hash_key)
+        .bind(kind)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// 将文件生成中的产物标记为失败(生成被中断时)
+pub async fn fail_running_artifacts(pool: &SqlitePool, hash_key: &str, error: &str) -> Result<()> {
+    sqlx::query(
+        "UPDATE artifact SET state = 'failed', error = ? WHERE hash_key = ? AND state = 'running'",
+    )
+    .bind(error)
+    .bind(hash_key)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 替换文件的所有关键帧时间点
+pub async fn replace_keyframes(
+    pool: &SqlitePool,
+    hash_key: &str,
+    keyframes: &[Keyframe],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM keyframe WHERE hash_key = ?")
+        .bind(hash_key)
+        .execute(&mut *tx)
+        .await?;
+    for keyframe in keyframes {
+        sqlx::query("INSERT INTO keyframe (hash_key, name, pts_time) VALUES (?, ?, ?)")
+            .bind(hash_key)
+            .bind(&keyframe.name)
+            .bind(keyframe.pts_time)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 查询文件的所有关键帧时间点, 按文件名排序
+pub async fn query_keyframes(pool: &SqlitePool, hash_key: &str) -> Result<Vec<Keyframe>> {
+    let list = sqlx::query_as::<_, Keyframe>(
+        "SELECT hash_key, name, pts_time FROM keyframe WHERE hash_key = ? ORDER BY name",
+    )
+    .bind(hash_key)
+    .fetch_all(pool)
+    .await?;
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        let cases = [
+            ("abc-123", "abc-123"),
+            ("", ""),
+            ("100%", "100\\%"),
+            ("a_b", "a\\_b"),
+            ("D:\\video", "D:\\\\video"),
+            ("\\%_", "\\\\\\%\\_"),
+            ("%%__", "\\%\\%\\_\\_"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(escape_like(input), expected, "escape_like({:?})", input);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::model::R;
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+#[cfg(windows)]
 use everything_sdk::EverythingError;
 use thiserror::Error;
 
@@ -8,13 +10,51 @@ pub enum IError {
     /// 数据库错误
     #[error("数据库(sqlx)错误")]
     DatabaseError(#[from] sqlx::Error),
+    #[cfg(windows)]
     #[error("everything错误")]
     EsError(#[from] EverythingError),
+    /// 搜索后端不可用
+    #[error("搜索后端不可用: {0}")]
+    SearchUnavailable(String),
+    /// 搜索索引尚未加载完成
+    #[error("搜索索引正在加载, 请稍后重试")]
+    IndexLoading,
+    /// 搜索结果中的文件信息无效
+    #[error("无效的文件信息: {0}")]
+    InvalidItem(String),
+    /// 没有匹配的文件
+    #[error("没有找到匹配的文件: {0}")]
+    NoSearchResult(String),
+    /// 请求的资源不存在
+    #[error("资源不存在: {0}")]
+    NotFound(String),
+    /// 请求参数无效
+    #[error("无效的请求参数: {0}")]
+    BadRequest(String),
+    /// 其他内部错误
+    #[error("内部错误: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl IError {
+    /// 对应的http状态码
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            IError::SearchUnavailable(_) | IError::IndexLoading => StatusCode::SERVICE_UNAVAILABLE,
+            IError::NoSearchResult(_) | IError::NotFound(_) => StatusCode::NOT_FOUND,
+            IError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            #[cfg(windows)]
+            IError::EsError(_) => StatusCode::BAD_GATEWAY,
+            IError::DatabaseError(_) | IError::InvalidItem(_) | IError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
 }
 
 impl IntoResponse for IError {
     fn into_response(self) -> Response {
         let body = format!("错误: {}", self);
-        R::<String>::err(-1, body).into_response()
+        (self.status_code(), R::<String>::err(-1, body)).into_response()
     }
 }
@@ -1,23 +1,40 @@
-use anyhow::Result;
+use crate::errors::IError;
+use crate::search::{FileSearcher, SdkFileItem, SearchRequest, SearchResult, SortOrder};
 use everything_sdk::{EverythingError, EverythingItem, RequestFlags, SortType};
-use serde::{Deserialize, Serialize};
-use tracing::info;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tracing::{info, warn};
+
+/// everything搜索后端(仅windows, 需要everything在后台运行)
+pub struct EverythingSearcher;
+
+impl FileSearcher for EverythingSearcher {
+    fn name(&self) -> &'static str {
+        "everything"
+    }
+
+    fn search<'a>(&'a self, req: &'a SearchRequest) -> BoxFuture<'a, Result<SearchResult, IError>> {
+        search_files(req).boxed()
+    }
+}
 
 /// 调用everything_sdk查询文件
-pub async fn search_files_by_keyword(keyword: String) -> Result<(String, Vec<SdkFileItem>)> {
+pub async fn search_files(req: &SearchRequest) -> Result<SearchResult, IError> {
     let start = std::time::Instant::now();
     //这里我们使用异步版本[`futures:：Mutex`]，所以等待它。
     let mut everything = everything_sdk::global().lock().await;
     let data = match everything.is_db_loaded() {
-        Ok(false) => panic!("Everything数据库现在尚未完全加载。"),
-        Err(EverythingError::Ipc) => panic!("everything需要在后台运行。"),
-        _ => {
+        Ok(false) => return Err(IError::IndexLoading),
+        Err(EverythingError::Ipc) => {
+            return Err(IError::SearchUnavailable(
+                "everything需要在后台运行".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+        Ok(true) => {
             // 创建一个搜索器
             let mut searcher = everything.searcher();
-            let keyword = format!(
-                "size:>128MB .mp4|.avi|.wmv|.mkv|.mpg|.rmvb|.iso|.bt.xltd {}",
-                keyword
-            );
+            let keyword = req.query_string();
             // 设置搜索关键字
             searcher.set_search(&keyword);
             // 设置搜索类型
@@ -31,64 +48,96 @@ pub async fn search_files_by_keyword(keyword: String) -> Result<(String, Vec<Sdk
                         | RequestFlags::EVERYTHING_REQUEST_EXTENSION,
                 )
                 // 最大结果数
-                .set_max(20)
-                // 忽略大小写
-                .set_match_case(false)
-                // 默认：按照文件名排序
-                .set_sort(SortType::EVERYTHING_SORT_NAME_ASCENDING);
+                .set_max(req.max_results)
+                // 分页偏移
+                .set_offset(req.offset)
+                // 是否区分大小写
+                .set_match_case(req.match_case)
+                // 排序方式
+                .set_sort(to_sort_type(req.sort));
             // 执行查询
             let results = searcher.query().await;
+            let total = results.total();
+            // 跳过无法解析的条目(如非UTF-8路径), 避免单个文件导致整个请求失败
             let list = results
                 .into_iter()
-                .map(|ei| SdkFileItem::from(ei))
+                .filter_map(|ei| match SdkFileItem::try_from(ei) {
+                    Ok(item) => Some(item),
+                    Err(e) => {
+                        warn!("跳过everything结果: {}", e);
+                        None
+                    }
+                })
                 .collect::<Vec<_>>();
-            (keyword, list)
+            SearchResult {
+                query: keyword,
+                total,
+                items: list,
+            }
         }
     };
     info!(
-        "es查询【{}】({})耗时: {:?}",
-        data.0,
-        data.1.len(),
+        "es查询【{}】({}/{})耗时: {:?}",
+        data.query,
+        data.items.len(),
+        data.total,
         start.elapsed()
     );
     Ok(data)
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct SdkFileItem {
-    pub index: u32,
-    // 完全路径
-    pub filepath: String,
-    // 文件名
-    pub filename: String,
-    // 扩展名
-    pub ext: String,
-    // 文件路径
-    pub path: String,
-    // 文件大小
-    pub size: u64,
-    // 创建时间
-    pub date_created: String,
-    // 是否是目录
-    pub is_dir: bool,
+fn to_sort_type(sort: SortOrder) -> SortType {
+    match sort {
+        SortOrder::NameAsc => SortType::EVERYTHING_SORT_NAME_ASCENDING,
+        SortOrder::NameDesc => SortType::EVERYTHING_SORT_NAME_DESCENDING,
+        SortOrder::PathAsc => SortType::EVERYTHING_SORT_PATH_ASCENDING,
+        SortOrder::PathDesc => SortType::EVERYTHING_SORT_PATH_DESCENDING,
+        SortOrder::SizeAsc => SortType::EVERYTHING_SORT_SIZE_ASCENDING,
+        SortOrder::SizeDesc => SortType::EVERYTHING_SORT_SIZE_DESCENDING,
+        SortOrder::DateCreatedAsc => SortType::EVERYTHING_SORT_DATE_CREATED_ASCENDING,
+        SortOrder::DateCreatedDesc => SortType::EVERYTHING_SORT_DATE_CREATED_DESCENDING,
+    }
 }
 
-impl<'a> From<EverythingItem<'a>> for SdkFileItem {
-    fn from(ei: EverythingItem<'a>) -> Self {
-        let binding = ei.filepath().unwrap();
-        let filepath = binding.to_str().unwrap();
-        let filename = ei.filename().unwrap().to_str().unwrap().to_string();
-        let is_dir = ei.is_folder();
-        SdkFileItem {
-            index: ei.index(),
-            filepath: filepath.into(),
-            filename: filename.clone(),
-            path: ei.path().unwrap().to_str().unwrap().into(),
-            ext: ei.extension().unwrap().into_string().unwrap(),
-            size: ei.size().unwrap(),
-            date_created: ei.date_created().unwrap().to_string(),
-            is_dir,
-        }
+impl<'a> TryFrom<EverythingItem<'a>> for SdkFileItem {
+    type Error = IError;
+
+    fn try_from(ei: EverythingItem<'a>) -> Result<Self, Self::Error> {
+        let index = ei.index();
+        let invalid =
+            |field: &str| IError::InvalidItem(format!("第{}条结果的{}无效", index, field));
+        let filepath = ei
+            .filepath()
+            .ok()
+            .and_then(|p| p.to_str().map(String::from))
+            .ok_or_else(|| invalid("filepath"))?;
+        let filename = ei
+            .filename()
+            .ok()
+            .and_then(|f| f.into_string().ok())
+            .ok_or_else(|| invalid("filename"))?;
+        let path = ei
+            .path()
+            .ok()
+            .and_then(|p| p.to_str().map(String::from))
+            .ok_or_else(|| invalid("path"))?;
+        // 没有扩展名的文件返回空字符串
+        let ext = ei
+            .extension()
+            .ok()
+            .and_then(|e| e.into_string().ok())
+            .unwrap_or_default();
+        let size = ei.size().map_err(|_| invalid("size"))?;
+        let date_created = ei.date_created().map(|d| d.to_string()).unwrap_or_default();
+        Ok(SdkFileItem {
+            index,
+            filepath,
+            filename,
+            ext,
+            path,
+            size,
+            date_created,
+            is_dir: ei.is_folder(),
+        })
     }
 }
@@ -1,190 +1,514 @@
-use crate::model::{CodeRequest, FileInfo, R};
-use crate::thumbnail::{self, gen_file_dir_path, OUTPUT_DIR};
-use crate::{dao, es};
-use async_walkdir::WalkDir;
-use axum::extract::{Query, State};
-use axum::response::{sse, IntoResponse, Sse};
+use crate::errors::IError;
+use crate::job::JobQueue;
+use crate::model::{
+    ArtifactKind, ArtifactState, CodeRequest, FileEvent, FileInfo, JobState, Page, PreviewMode,
+    SearchItem, ThumbnailEvent, ThumbnailImage, ThumbnailStatus, R,
+};
+use crate::search::{FileSearcher, SearchRequest};
+use crate::thumbnail::{self, gen_hash_dir_path, OUTPUT_DIR};
+use crate::{dao, fhash, serve};
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{sse, IntoResponse, Response, Sse};
 use base64::engine::general_purpose;
 use base64::Engine;
+use dotenvy::{dotenv, var};
 use futures::future::join_all;
-use futures::Stream as FuturesStream;
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use futures::{Stream as FuturesStream, StreamExt};
 use sqlx::SqlitePool;
+use std::convert::Infallible;
 use std::path::PathBuf;
-use std::pin::Pin;
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
-use tokio_stream::StreamExt as TokioStreamExt;
-use tracing::{error, info};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
 
-/// 获取视频缩略图
+/// SSE推送配置
+pub static SSE: LazyLock<SseConfig> = LazyLock::new(|| {
+    dotenv().ok();
+    SseConfig::from_env()
+});
+
+/// SSE推送配置
+#[derive(Debug, Clone)]
+pub struct SseConfig {
+    // 空闲超时, 超过该时间没有新事件时推送error并结束
+    pub idle_timeout: Duration,
+    // 保活注释的发送间隔
+    pub keep_alive: Duration,
+    // 同时处理的文件数
+    pub parallel: usize,
+}
+
+impl SseConfig {
+    /// 从环境变量读取
+    /// `SSE_IDLE_TIMEOUT_SECS`: 空闲超时(秒), 默认120
+    /// `SSE_KEEP_ALIVE_SECS`: 保活间隔(秒), 默认15
+    /// `SSE_PARALLEL`: 同时处理的文件数, 默认2
+    pub fn from_env() -> Self {
+        let idle_timeout = var("SSE_IDLE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(120);
+        let keep_alive = var("SSE_KEEP_ALIVE_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(15);
+        let parallel = var("SSE_PARALLEL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2);
+        Self {
+            idle_timeout: Duration::from_secs(idle_timeout),
+            keep_alive: Duration::from_secs(keep_alive),
+            parallel: usize::max(parallel, 1),
+        }
+    }
+}
+
+/// 分页搜索文件, 附带已入库的文件信息和缩略图状态
+pub async fn search(
+    // 接收查询参数
+    Query(code_req): Query<CodeRequest>,
+    // 数据库连接池
+    State(pool): State<SqlitePool>,
+    // 搜索后端
+    State(searcher): State<Arc<dyn FileSearcher>>,
+) -> Result<impl IntoResponse, IError> {
+    let req = SearchRequest::from(&code_req);
+    let preview = code_req.preview.unwrap_or(*thumbnail::PREVIEW);
+    let result = searcher.search(&req).await?;
+    let file_paths = result
+        .items
+        .iter()
+        .map(|file| file.filepath.clone())
+        .collect::<Vec<_>>();
+    let infos = dao::query_by_file_paths(&pool, &file_paths).await?;
+    let mut items = Vec::with_capacity(result.items.len());
+    for file in result.items {
+        let info = infos.iter().find(|fi| fi.file_path == file.filepath);
+        let thumbnail_status = match info {
+            Some(fi) => thumbnail::thumbnail_status(&pool, &fi.hash_key, preview).await?,
+            None => ThumbnailStatus::None,
+        };
+        items.push(SearchItem {
+            indexed: info.is_some(),
+            file_id: info.map(|fi| fi.id),
+            hash_key: info.map(|fi| fi.hash_key.clone()),
+            total_frame: info.map(|fi| fi.total_frame),
+            video_url: info.map(|fi| serve::video_url(&fi.hash_key)),
+            thumbnail_track_url: info.and_then(|fi| thumbnail::thumbnail_track_url(&fi.hash_key)),
+            contact_sheet_url: info.and_then(|fi| thumbnail::contact_sheet_url(&fi.hash_key)),
+            thumbnail_status,
+            file,
+        });
+    }
+    Ok(R::ok(Page {
+        query: result.query,
+        total: result.total,
+        offset: req.offset,
+        limit: req.max_results,
+        items,
+    }))
+}
+
+/// 获取视频缩略图, 未生成的加入后台任务队列, 生成完成后再次请求即可获取
 pub async fn get_thumbnails(
     // 接收查询参数
     Query(code_req): Query<CodeRequest>,
     // 数据库连接池
     State(pool): State<SqlitePool>,
-) -> impl IntoResponse {
-    let code = code_req.code.clone();
-    let files = es::search_files_by_keyword(code).await.unwrap();
-    let tasks = files.1.iter().map(async |file| {
-        let start = std::time::Instant::now();
-        let info = dao::query_and_update_by_file_path(&pool, &file.filepath)
-            .await
-            .unwrap();
-        let out_dir = OUTPUT_DIR.as_str();
-        let file_dir_path = gen_file_dir_path(out_dir, &FileInfo::obtain_filename(&info.file_path));
-        let gif_path = thumbnail::gen_out_gif_path(&file_dir_path);
-        let encodeds = get_files_to_base64_by_dir(&gif_path);
-        info!("文件耗时: {:?}", start.elapsed());
-        encodeds
-    });
-    // 并发执行所有任务,并且拍平收集结果Vec<String>
+    // 搜索后端
+    State(searcher): State<Arc<dyn FileSearcher>>,
+    // 缩略图任务队列
+    State(jobs): State<JobQueue>,
+) -> Result<impl IntoResponse, IError> {
+    let req = SearchRequest::from(&code_req);
+    let base64 = code_req.base64.unwrap_or(false);
+    let preview = code_req.preview.unwrap_or(*thumbnail::PREVIEW);
+    let files = searcher.search(&req).await?;
+    let tasks = files
+        .items
+        .iter()
+        .map(async |file| -> Result<Vec<ThumbnailImage>, IError> {
+            let start = std::time::Instant::now();
+            // 文件不可读或已删除(本地索引定期重建, 可能包含已删除的文件)时跳过
+            let hash_key = match fhash::compute_sample_hash(&file.filepath) {
+                Ok(hash_key) => hash_key,
+                Err(e) => {
+                    warn!("计算文件hash失败, 跳过 {}: {}", file.filepath, e);
+                    return Ok(vec![]);
+                }
+            };
+            // 未入库或产物未按当前参数生成时加入任务队列
+            let status = thumbnail::thumbnail_status(&pool, &hash_key, preview).await?;
+            if status != ThumbnailStatus::Ready {
+                jobs.enqueue(&hash_key, &file.filepath, preview).await?;
+                return Ok(vec![]);
+            }
+            let file_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), &hash_key);
+            let mut images = vec![];
+            if preview.includes_gif() {
+                let gif_path = thumbnail::gen_out_gif_path(&file_dir_path);
+                images.extend(get_thumbnail_images_by_dir(&hash_key, &gif_path, base64));
+            }
+            if preview.includes_clip()
+                && let Some(url) = thumbnail::teaser_url(&hash_key)
+            {
+                images.push(ThumbnailImage {
+                    url,
+                    data: None,
+                    pts_time: None,
+                });
+            }
+            info!("文件耗时: {:?}", start.elapsed());
+            Ok(images)
+        });
+    // 并发执行所有任务,并且拍平收集结果Vec<ThumbnailImage>
     let res = join_all(tasks)
         .await
-        .iter()
-        .flat_map(|l| l.clone())
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    Ok(R::ok(res))
+}
+
+/// 获取缩略图文件(关键帧、预览动图等), 支持协商缓存
+pub async fn get_thumbnail_file(
+    Path((hash_key, name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, IError> {
+    let path = thumbnail::find_thumbnail_file(&hash_key, &name)
+        .ok_or_else(|| IError::NotFound(format!("{}/{}", hash_key, name)))?;
+    serve::serve_file(&path, &headers, serve::THUMBNAIL_CACHE_CONTROL).await
+}
+
+/// 获取关键帧图片及其在视频中的时间点, 用于点击缩略图跳转播放
+pub async fn get_keyframes(
+    Path(hash_key): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> Result<impl IntoResponse, IError> {
+    let keyframes = dao::query_keyframes(&pool, &hash_key)
+        .await?
+        .into_iter()
+        .map(|keyframe| ThumbnailImage {
+            url: thumbnail::thumbnail_url(&hash_key, &keyframe.name),
+            data: None,
+            pts_time: Some(keyframe.pts_time),
+        })
         .collect::<Vec<_>>();
-    R::ok(res)
+    Ok(R::ok(keyframes))
+}
+
+/// 获取文件的缩略图任务状态, 执行中时附带进度和预计剩余时间
+pub async fn get_job_status(
+    Path(hash_key): Path<String>,
+    State(jobs): State<JobQueue>,
+) -> Result<impl IntoResponse, IError> {
+    let status = jobs
+        .status(&hash_key)
+        .await?
+        .ok_or_else(|| IError::NotFound(format!("任务: {}", hash_key)))?;
+    Ok(R::ok(status))
 }
 
-// 指定目录下所有文件(1级目录),并转为base64
-fn get_files_to_base64_by_dir(dir: &str) -> Vec<String> {
-    let mut encodeds = vec![];
+/// 取消文件的缩略图任务(排队中或执行中), 返回取消后的任务
+pub async fn cancel_job(
+    Path(hash_key): Path<String>,
+    State(jobs): State<JobQueue>,
+) -> Result<impl IntoResponse, IError> {
+    let job = jobs
+        .cancel(&hash_key)
+        .await?
+        .ok_or_else(|| IError::NotFound(format!("任务: {}", hash_key)))?;
+    Ok(R::ok(job))
+}
+
+/// 按文件id播放视频, 支持Range请求
+pub async fn get_video_by_id(
+    Path(id): Path<u32>,
+    State(pool): State<SqlitePool>,
+    headers: HeaderMap,
+) -> Result<Response, IError> {
+    let file_info = dao::query_by_id(&pool, id)
+        .await?
+        .ok_or_else(|| IError::NotFound(format!("文件id: {}", id)))?;
+    stream_video(&pool, &file_info, &headers).await
+}
+
+/// 按文件hash_key播放视频, 支持Range请求
+pub async fn get_video_by_hash(
+    Path(hash_key): Path<String>,
+    State(pool): State<SqlitePool>,
+    headers: HeaderMap,
+) -> Result<Response, IError> {
+    let file_info = dao::query_by_hash_key(&pool, &hash_key)
+        .await?
+        .ok_or_else(|| IError::NotFound(format!("文件hash: {}", hash_key)))?;
+    stream_video(&pool, &file_info, &headers).await
+}
+
+async fn stream_video(
+    pool: &SqlitePool,
+    file_info: &FileInfo,
+    headers: &HeaderMap,
+) -> Result<Response, IError> {
+    let container = dao::query_media_info(pool, file_info.id)
+        .await?
+        .map(|info| info.container);
+    let mime = serve::video_mime(container.as_deref(), &file_info.file_path);
+    serve::serve_file_with_mime(
+        std::path::Path::new(&file_info.file_path),
+        headers,
+        serve::VIDEO_CACHE_CONTROL,
+        Some(mime),
+    )
+    .await
+}
+
+// 指定目录下所有文件(1级目录)的缩略图url
+fn get_thumbnail_images_by_dir(hash_key: &str, dir: &str, base64: bool) -> Vec<ThumbnailImage> {
     info!("读取目录: {}", dir);
-    let paths = std::fs::read_dir(dir).unwrap();
-    for path in paths {
-        let path = path.unwrap().path();
-        if path.is_file() {
-            encodeds.push(gen_imgbase64_by_path(path));
-        }
-    }
-    encodeds
+    let Ok(paths) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    paths
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .filter_map(|path| gen_thumbnail_image(hash_key, path, base64, None))
+        .collect()
+}
+
+// 缩略图文件对应的url, base64为true时附带data uri, 读取失败时返回None
+fn gen_thumbnail_image(
+    hash_key: &str,
+    path: PathBuf,
+    base64: bool,
+    pts_time: Option<f64>,
+) -> Option<ThumbnailImage> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let data = if base64 {
+        Some(gen_imgbase64_by_path(path)?)
+    } else {
+        None
+    };
+    Some(ThumbnailImage {
+        url: thumbnail::thumbnail_url(hash_key, &name),
+        data,
+        pts_time,
+    })
 }
 
-pub fn gen_imgbase64_by_path(path: PathBuf) -> String {
+/// 读取图片文件并转换为data uri, 读取失败(如文件已被删除)时返回None
+pub fn gen_imgbase64_by_path(path: PathBuf) -> Option<String> {
     let mime = match path.extension() {
-        Some(ext) => match ext.to_str().unwrap().to_lowercase().as_str() {
+        Some(ext) => match ext.to_string_lossy().to_lowercase().as_str() {
             "png" => "image/png",
             "jpg" | "jpeg" => "image/jpeg",
             "gif" => "image/gif",
             "webp" => "image/webp",
+            "avif" => "image/avif",
             "svg" => "image/svg+xml",
             _ => "application/octet-stream",
         },
         None => "application/octet-stream",
     };
-    let file = std::fs::read(path).unwrap();
+    let file = match std::fs::read(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("读取图片失败 {}: {}", path.display(), e);
+            return None;
+        }
+    };
     let encoded = general_purpose::STANDARD.encode(&file);
-    format!("data:{};base64,{}", mime, encoded)
+    Some(format!("data:{};base64,{}", mime, encoded))
+}
+
+// 转换为SSE事件, 数据附带所属文件, base64为true时为关键帧和预览动图附带data uri
+fn gen_sse_event(
+    hash_key: &str,
+    file_path: &str,
+    mut event: ThumbnailEvent,
+    base64: bool,
+) -> sse::Event {
+    if base64 {
+        match &mut event {
+            ThumbnailEvent::Frame { url, data, .. } | ThumbnailEvent::Gif { url, data } => {
+                *data = url
+                    .rsplit('/')
+                    .next()
+                    .and_then(|name| thumbnail::find_thumbnail_file(hash_key, name))
+                    .and_then(gen_imgbase64_by_path);
+            }
+            _ => {}
+        }
+    }
+    let name = event.name();
+    let file_event = FileEvent {
+        hash_key: hash_key.to_string(),
+        file_path: file_path.to_string(),
+        event,
+    };
+    sse::Event::default()
+        .event(name)
+        .json_data(&file_event)
+        .unwrap_or_else(|e| sse::Event::default().event("error").data(e.to_string()))
+}
+
+// 是否推送该事件: 预览动图和短视频预览片段按预览方式过滤
+fn is_wanted_event(event: &ThumbnailEvent, preview: PreviewMode) -> bool {
+    match event {
+        ThumbnailEvent::Gif { .. } => preview.includes_gif(),
+        ThumbnailEvent::Clip { .. } => preview.includes_clip(),
+        _ => true,
+    }
 }
 
-/// 新的SSE处理器，监听文件变化并添加超时
+/// 推送所有匹配文件的缩略图生成事件(SSE), 每个事件附带所属文件的hashKey和filePath
+/// 按搜索结果顺序处理, 同时处理的文件数不超过配置的并发数, 全部结束后推送complete,
+/// 没有匹配的文件时只推送empty
 pub async fn sse_handler(
     // 接收查询参数
     Query(code_req): Query<CodeRequest>,
     // 数据库连接池
     State(pool): State<SqlitePool>,
-) -> Sse<impl FuturesStream<Item = Result<sse::Event, std::convert::Infallible>>> {
-    let code = code_req.code.clone();
-    let file = es::search_files_by_keyword(code)
-        .await
-        .unwrap()
-        .1
-        .first()
-        .unwrap()
-        .clone();
-    let out_dir = OUTPUT_DIR.as_str();
-    // 输出目录
-    let out_file_dir_path = gen_file_dir_path(out_dir, &FileInfo::obtain_filename(&file.filepath));
-    // 输出gif路径
-    let _gif_path = thumbnail::gen_out_gif_path(&out_file_dir_path);
-    // 输出png路径
-    let _png_path = thumbnail::gen_out_png_path(&out_file_dir_path);
-    let file_info = dao::query_by_file_path(&pool, &file.filepath)
-        .await
-        .unwrap();
-    let stream = match file_info {
-        None => {
-            tokio::spawn(async move {
-                // 生成缩略图
-                dao::create_file_info(&pool, &file.filepath).await.unwrap();
-            });
-            convert_pin_box_stream(gen_watch_dir_stream(out_file_dir_path))
-        }
-        Some(_) => convert_pin_box_stream(gen_read_current_file_stream(out_file_dir_path)),
-    };
-    Sse::new(stream)
-}
-fn convert_pin_box_stream<I>(
-    stream: impl FuturesStream<Item = I> + Send + 'static,
-) -> Pin<Box<dyn FuturesStream<Item = I> + Send>> {
-    Box::pin(stream)
-}
-
-fn gen_read_current_file_stream(
-    path: String,
-) -> impl FuturesStream<Item = Result<sse::Event, std::convert::Infallible>> {
-    info!("读取目录: {}", path);
-    Box::pin(async_stream::stream! {
-        let mut entries = WalkDir::new(path);
-        loop {
-            match entries.next().await {
-                Some(Ok(entry)) => {
-                    let ft = entry.file_type().await.unwrap();
-                    if ft.is_file() {
-                        yield Ok(sse::Event::default().data(gen_imgbase64_by_path(entry.path().to_path_buf())));
-                    }
-                },
-                Some(Err(e)) => {
-                    error!("读取文件夹失败: {}", e);
-                    break;
-                },
-                None => break,
+    // 搜索后端
+    State(searcher): State<Arc<dyn FileSearcher>>,
+    // 缩略图任务队列
+    State(jobs): State<JobQueue>,
+) -> Result<Sse<impl FuturesStream<Item = Result<sse::Event, Infallible>>>, IError> {
+    let req = SearchRequest::from(&code_req);
+    let files = searcher.search(&req).await?.items;
+    let base64 = code_req.base64.unwrap_or(false);
+    let preview = code_req.preview.unwrap_or(*thumbnail::PREVIEW);
+    let code = code_req.code;
+    let total = files.len();
+    // 关闭服务时结束推送
+    let closed = Box::pin(jobs.closed());
+    let shutdown = jobs.clone();
+    let mut file_streams = futures::stream::iter(files)
+        .map(move |file| {
+            Box::pin(gen_file_event_stream(
+                pool.clone(),
+                jobs.clone(),
+                file.filepath,
+                base64,
+                preview,
+            ))
+        })
+        .flatten_unordered(SSE.parallel)
+        .take_until(closed);
+    let stream = async_stream::stream! {
+        if total == 0 {
+            yield Ok(gen_json_event("empty", serde_json::json!({ "code": code })));
+        } else {
+            while let Some(event) = file_streams.next().await {
+                yield Ok(event);
+            }
+            if shutdown.is_closed() {
+                yield Ok(gen_json_event("shutdown", serde_json::json!({ "message": "服务关闭" })));
+            } else {
+                yield Ok(gen_json_event("complete", serde_json::json!({ "files": total })));
             }
         }
-    })
+    };
+    Ok(Sse::new(stream).keep_alive(sse::KeepAlive::new().interval(SSE.keep_alive)))
 }
 
-fn gen_watch_dir_stream(
-    path: String,
-) -> impl futures::Stream<Item = Result<sse::Event, std::convert::Infallible>> {
-    info!("监听目录: {}", path);
-    // 不存在则创建
-    if !std::path::Path::new(&path).exists() {
-        std::fs::create_dir_all(&path).unwrap();
-    }
+// 不属于单个文件的SSE事件
+fn gen_json_event(name: &str, data: serde_json::Value) -> sse::Event {
+    sse::Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|_| sse::Event::default().event(name))
+}
+
+// 单个文件的事件流: 已生成的直接推送, 未生成的加入任务队列并推送生成流程发出的事件,
+// 完成、失败或空闲超时后结束
+fn gen_file_event_stream(
+    pool: SqlitePool,
+    jobs: JobQueue,
+    file_path: String,
+    base64: bool,
+    preview: PreviewMode,
+) -> impl FuturesStream<Item = sse::Event> {
     async_stream::stream! {
-        // 创建监听器和接收通道
-        let (mut watcher, mut rx) = async_watcher().expect("创建watcher失败");
-        // 添加监听路径 NonRecursive: 只监听当前目录
-        watcher.watch(path.as_ref(), RecursiveMode::Recursive).unwrap();
-        // 循环处理事件（使用 tokio 的 next 方法）
-        while let Some(Ok(res)) = rx.recv().await  {
-            if let EventKind::Create(_) = res.kind {
-                yield Ok(sse::Event::default().data(gen_imgbase64_by_path(res.paths[0].to_path_buf())));
+        let hash_key = match fhash::compute_sample_hash(&file_path) {
+            Ok(hash_key) => hash_key,
+            Err(e) => {
+                let event = ThumbnailEvent::Error {
+                    message: format!("计算文件hash失败: {}", e),
+                    will_retry: false,
+                };
+                yield gen_sse_event("", &file_path, event, false);
+                return;
+            }
+        };
+        // 先订阅再加入任务, 以免错过事件
+        let mut rx = jobs.subscribe();
+        let _watch = jobs.watch(&hash_key);
+        let events = match initial_events(&pool, &jobs, &hash_key, &file_path, preview).await {
+            Ok(events) => events,
+            Err(e) => vec![ThumbnailEvent::Error {
+                message: e.to_string(),
+                will_retry: false,
+            }],
+        };
+        let mut finished = false;
+        for event in events.into_iter().filter(|event| is_wanted_event(event, preview)) {
+            finished = event.is_terminal();
+            yield gen_sse_event(&hash_key, &file_path, event, base64);
+        }
+        while !finished {
+            let event = match tokio::time::timeout(SSE.idle_timeout, rx.recv()).await {
+                Ok(Ok(job_event)) if job_event.hash_key == hash_key => job_event.event,
+                Ok(Ok(_)) => continue,
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    warn!("SSE事件处理过慢, 丢失{}个事件", skipped);
+                    continue;
+                }
+                Ok(Err(RecvError::Closed)) => break,
+                Err(_) => ThumbnailEvent::Error {
+                    message: format!("{:?}内没有新的进度, 结束推送", SSE.idle_timeout),
+                    will_retry: false,
+                },
+            };
+            if is_wanted_event(&event, preview) {
+                finished = event.is_terminal();
+                yield gen_sse_event(&hash_key, &file_path, event, base64);
             }
         }
-        // 自动清理
-        watcher.unwatch(path.as_ref()).unwrap();
     }
 }
 
-/// 监听者
-fn async_watcher() -> notify::Result<(
-    RecommendedWatcher,
-    tokio::sync::mpsc::Receiver<notify::Result<notify::Event>>,
-)> {
-    let (tx, rx) = tokio::sync::mpsc::channel(10);
-    // 监听器配置
-    let config = Config::default()
-        // 设置防抖时间
-        .with_poll_interval(Duration::from_millis(50));
-    let watcher = RecommendedWatcher::new(
-        move |res| {
-            futures::executor::block_on(async {
-                tx.send(res).await.unwrap();
-            })
-        },
-        config,
-    )?;
-    Ok((watcher, rx))
+// 文件开始推送时的事件: 预览方式需要的缩略图都已生成时为全部已生成的缩略图, 否则加入任务队列
+async fn initial_events(
+    pool: &SqlitePool,
+    jobs: &JobQueue,
+    hash_key: &str,
+    file_path: &str,
+    preview: PreviewMode,
+) -> Result<Vec<ThumbnailEvent>, IError> {
+    if thumbnail::thumbnail_status(pool, hash_key, preview).await? == ThumbnailStatus::Ready {
+        return Ok(thumbnail::current_events(pool, hash_key).await?);
+    }
+    let job = jobs.enqueue(hash_key, file_path, preview).await?;
+    let mut events = vec![ThumbnailEvent::Queued {
+        job_id: job.id,
+        state: job.state,
+    }];
+    // 任务已在执行中时可能错过了关键帧事件, 先推送已生成的关键帧
+    let keyframes_done = dao::query_artifact(pool, hash_key, ArtifactKind::Keyframes)
+        .await?
+        .is_some_and(|artifact| artifact.state == ArtifactState::Done);
+    if job.state == JobState::Running && keyframes_done {
+        let keyframes = dao::query_keyframes(pool, hash_key).await?;
+        events.extend(thumbnail::keyframe_events(hash_key, &keyframes));
+    }
+    Ok(events)
 }
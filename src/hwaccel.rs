@@ -0,0 +1,152 @@
+use crate::process;
+use anyhow::Result;
+use dotenvy::{dotenv, var};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::{LazyLock, Mutex};
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// 硬件加速配置(环境变量`HWACCEL`), 默认auto
+pub static HWACCEL: LazyLock<HwAccel> = LazyLock::new(|| {
+    dotenv().ok();
+    var("HWACCEL")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(HwAccel::Auto)
+});
+
+/// auto模式下按顺序选择第一个ffmpeg支持的硬件加速
+const AUTO_PREFERENCE: &[HwAccel] = &[HwAccel::Cuda, HwAccel::Qsv, HwAccel::Vaapi];
+
+// 检测结果, 只检测一次
+static RESOLVED: OnceCell<Option<HwAccel>> = OnceCell::const_new();
+
+// 硬件解码实际执行失败后改用软件解码的视频编码格式(如设备不支持10bit HEVC或AV1):
+// ffmpeg -hwaccels列出的是编译进ffmpeg的方式, 不代表本机能解码所有格式
+static FALLBACK_CODECS: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// 硬件解码方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HwAccel {
+    // 软件解码
+    None,
+    // 自动检测
+    Auto,
+    Cuda,
+    Vaapi,
+    Qsv,
+}
+
+impl HwAccel {
+    /// ffmpeg `-hwaccel`参数值
+    pub fn as_ffmpeg_arg(&self) -> Option<&'static str> {
+        match self {
+            HwAccel::None => None,
+            HwAccel::Auto => Some("auto"),
+            HwAccel::Cuda => Some("cuda"),
+            HwAccel::Vaapi => Some("vaapi"),
+            HwAccel::Qsv => Some("qsv"),
+        }
+    }
+}
+
+impl FromStr for HwAccel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hwaccel = match s.to_lowercase().as_str() {
+            "none" | "cpu" | "" => HwAccel::None,
+            "auto" => HwAccel::Auto,
+            "cuda" | "nvdec" => HwAccel::Cuda,
+            "vaapi" => HwAccel::Vaapi,
+            "qsv" => HwAccel::Qsv,
+            _ => return Err(anyhow::anyhow!("未知的硬件加速: {}", s)),
+        };
+        Ok(hwaccel)
+    }
+}
+
+/// 根据配置、本机ffmpeg支持情况和视频编码格式(media_stream.codec_name)确定实际使用的硬件加速,
+/// None表示软件解码
+pub async fn resolve(codec: Option<&str>) -> Option<HwAccel> {
+    if let Some(codec) = codec
+        && let Ok(fallback) = FALLBACK_CODECS.lock()
+        && fallback.contains(codec)
+    {
+        return None;
+    }
+    *RESOLVED
+        .get_or_init(|| async {
+            let configured = *HWACCEL;
+            if configured == HwAccel::None {
+                info!("硬件加速: 未启用");
+                return None;
+            }
+            let supported = match detect_supported().await {
+                Ok(supported) => supported,
+                Err(e) => {
+                    warn!("检测ffmpeg硬件加速失败, 使用软件解码: {}", e);
+                    return None;
+                }
+            };
+            let is_supported = |hw: &HwAccel| {
+                supported
+                    .iter()
+                    .any(|s| Some(s.as_str()) == hw.as_ffmpeg_arg())
+            };
+            let resolved = match configured {
+                HwAccel::Auto => AUTO_PREFERENCE.iter().copied().find(is_supported),
+                hw if is_supported(&hw) => Some(hw),
+                hw => {
+                    warn!("ffmpeg不支持硬件加速{:?}, 使用软件解码", hw);
+                    None
+                }
+            };
+            info!(
+                "硬件加速: 配置{:?}, ffmpeg支持{:?}, 使用{:?}",
+                configured, supported, resolved
+            );
+            resolved
+        })
+        .await
+}
+
+/// 硬件解码执行失败, 之后同一编码格式的视频都使用软件解码, 避免每次先失败一次再重试;
+/// 编码格式未知时不记录, 其他格式的视频仍使用硬件解码
+pub fn fall_back_to_software(hwaccel: HwAccel, codec: Option<&str>) {
+    let Some(codec) = codec else {
+        return;
+    };
+    if let Ok(mut fallback) = FALLBACK_CODECS.lock()
+        && fallback.insert(codec.to_string())
+    {
+        warn!(
+            "硬件解码{:?}解码{}失败, 之后{}格式的视频使用软件解码",
+            hwaccel, codec, codec
+        );
+    }
+}
+
+/// 查询本机ffmpeg支持的硬件加速: ffmpeg -hide_banner -hwaccels
+async fn detect_supported() -> Result<Vec<String>> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-hide_banner").arg("-hwaccels");
+    let deadline = Instant::now() + process::TIMEOUT.probe;
+    let output = process::output(cmd, deadline, None).await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("ffmpeg -hwaccels失败: {}", stderr));
+    }
+    // 输出格式: 第一行为"Hardware acceleration methods:", 之后每行一个
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .skip(1)
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
@@ -1,4 +1,4 @@
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::{
     fmt::{self, time::ChronoLocal},
     layer::SubscriberExt,
@@ -26,3 +26,32 @@ pub fn log() {
         .init();
     info!("初始化日志成功");
 }
+
+/// 等待退出信号: Ctrl-C(SIGINT)或SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("监听Ctrl-C失败: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                warn!("监听SIGTERM失败: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => info!("收到SIGINT"),
+        _ = terminate => info!("收到SIGTERM"),
+    }
+}
@@ -0,0 +1,777 @@
+use crate::model::{JobProgress, JobState, JobStatus, PreviewMode, ThumbnailEvent, ThumbnailJob};
+use crate::{dao, process, thumbnail};
+use anyhow::Result;
+use dotenvy::var;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, watch, Notify};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// 没有新任务通知时的轮询间隔(用于执行到期的重试任务)
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 事件通道容量, 订阅者处理过慢时会丢失较早的事件
+const EVENT_CAPACITY: usize = 1024;
+
+/// 任务取消时记录的错误信息
+const CANCELLED: &str = "任务已取消";
+
+/// 关闭服务时中断的任务记录的错误信息
+const INTERRUPTED: &str = "服务关闭, 任务中断";
+
+/// 任务事件, hash_key标识所属文件
+#[derive(Debug, Clone)]
+pub struct JobEvent {
+    pub hash_key: String,
+    pub event: ThumbnailEvent,
+}
+
+// 任务队列的关闭状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shutdown {
+    // 正常运行
+    None,
+    // 不再领取新任务, 等待执行中的任务完成
+    Draining,
+    // 等待超时, 中断执行中的任务
+    Aborting,
+}
+
+// 单个任务的执行结果
+enum Outcome {
+    // 生成完成时为关键帧数
+    Finished(Result<u32>),
+    // 通过接口或断开连接取消
+    Cancelled,
+    // 关闭服务时中断
+    Interrupted,
+}
+
+/// 缩略图任务队列(持久化在sqlite), 由固定数量的worker并发执行
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: SqlitePool,
+    // 新任务通知
+    notify: Arc<Notify>,
+    // 生成事件广播
+    events: broadcast::Sender<JobEvent>,
+    // 执行中任务的最新进度, key为hash_key
+    progress: Arc<Mutex<HashMap<String, JobProgress>>>,
+    // 执行中任务的取消通知, key为hash_key
+    running: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    // 领取任务(并登记取消通知)与取消任务互斥, 取消时任务要么仍在排队, 要么已登记取消通知
+    claim: Arc<tokio::sync::Mutex<()>>,
+    // 正在推送事件的连接数, key为hash_key
+    watchers: Arc<Mutex<HashMap<String, usize>>>,
+    // 文件的所有推送连接都断开时是否取消任务
+    cancel_unwatched: bool,
+    // 关闭状态
+    shutdown: Arc<watch::Sender<Shutdown>>,
+    // 已启动的worker
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    // worker数量
+    workers: usize,
+    // 最大执行次数
+    max_attempts: u32,
+    // 重试退避基础时间, 第n次重试等待 base * 2^(n-1)
+    retry_base: Duration,
+}
+
+impl JobQueue {
+    pub fn new(
+        pool: SqlitePool,
+        workers: usize,
+        max_attempts: u32,
+        retry_base: Duration,
+        cancel_unwatched: bool,
+    ) -> Self {
+        Self {
+            pool,
+            notify: Arc::new(Notify::new()),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            progress: Arc::new(Mutex::new(HashMap::new())),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            claim: Arc::new(tokio::sync::Mutex::new(())),
+            watchers: Arc::new(Mutex::new(HashMap::new())),
+            cancel_unwatched,
+            shutdown: Arc::new(watch::channel(Shutdown::None).0),
+            handles: Arc::new(Mutex::new(Vec::new())),
+            workers: workers.max(1),
+            max_attempts: max_attempts.max(1),
+            retry_base,
+        }
+    }
+
+    /// 从环境变量创建
+    /// `THUMBNAIL_WORKERS`: worker数量, 默认2
+    /// `THUMBNAIL_MAX_ATTEMPTS`: 最大执行次数, 默认3
+    /// `THUMBNAIL_RETRY_BASE_SECS`: 重试退避基础时间(秒), 默认30
+    /// `THUMBNAIL_CANCEL_ON_DISCONNECT`: 文件的所有推送连接都断开时取消任务, 默认false
+    pub fn from_env(pool: SqlitePool) -> Self {
+        let workers = var("THUMBNAIL_WORKERS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2);
+        let max_attempts = var("THUMBNAIL_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(3);
+        let retry_base_secs = var("THUMBNAIL_RETRY_BASE_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30);
+        let cancel_unwatched = var("THUMBNAIL_CANCEL_ON_DISCONNECT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(false);
+        Self::new(
+            pool,
+            workers,
+            max_attempts,
+            Duration::from_secs(retry_base_secs),
+            cancel_unwatched,
+        )
+    }
+
+    /// 加入任务, 同一个文件(hash_key)重复加入不会产生新任务, preview为需要生成的预览方式
+    pub async fn enqueue(
+        &self,
+        hash_key: &str,
+        file_path: &str,
+        preview: PreviewMode,
+    ) -> Result<ThumbnailJob> {
+        let job = dao::enqueue_job(&self.pool, hash_key, file_path, preview, now_millis()).await?;
+        info!("缩略图任务#{} {:?}: {}", job.id, job.state, job.file_path);
+        self.notify.notify_one();
+        Ok(job)
+    }
+
+    /// 订阅所有任务的生成事件, 需要在加入任务前订阅以免错过事件
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    /// 登记文件的推送连接, 返回的守卫drop时注销; 配置了断开取消时, 最后一个连接断开会取消任务
+    pub fn watch(&self, hash_key: &str) -> Watch {
+        if let Ok(mut watchers) = self.watchers.lock() {
+            *watchers.entry(hash_key.to_string()).or_default() += 1;
+        }
+        Watch {
+            queue: self.clone(),
+            hash_key: hash_key.to_string(),
+        }
+    }
+
+    /// 取消文件的任务: 排队中的任务直接标记为已取消, 执行中的任务通知worker中断(结束ffmpeg进程)
+    /// 任务不存在时返回None, 已结束的任务不受影响
+    pub async fn cancel(&self, hash_key: &str) -> Result<Option<ThumbnailJob>> {
+        let _claim = self.claim.lock().await;
+        let Some(job) = self.query(hash_key).await? else {
+            return Ok(None);
+        };
+        if job.state == JobState::Queued
+            && dao::cancel_job(
+                &self.pool,
+                hash_key,
+                JobState::Queued,
+                CANCELLED,
+                now_millis(),
+            )
+            .await?
+        {
+            info!("取消排队中的任务#{}: {}", job.id, job.file_path);
+            self.emit(
+                hash_key,
+                ThumbnailEvent::Error {
+                    message: CANCELLED.to_string(),
+                    will_retry: false,
+                },
+            );
+        } else if let Ok(running) = self.running.lock()
+            && let Some(cancel) = running.get(hash_key)
+        {
+            // worker尚未开始等待时notify_one会保留通知
+            cancel.notify_one();
+        }
+        self.query(hash_key).await
+    }
+
+    // 广播事件(没有订阅者时忽略), 并记录最新进度
+    fn emit(&self, hash_key: &str, event: ThumbnailEvent) {
+        if let Ok(mut progress) = self.progress.lock() {
+            match &event {
+                ThumbnailEvent::Progress(p) => {
+                    progress.insert(hash_key.to_string(), p.clone());
+                }
+                ThumbnailEvent::Done { .. } | ThumbnailEvent::Error { .. } => {
+                    progress.remove(hash_key);
+                }
+                _ => {}
+            }
+        }
+        let _ = self.events.send(JobEvent {
+            hash_key: hash_key.to_string(),
+            event,
+        });
+    }
+
+    /// 查询文件的任务
+    pub async fn query(&self, hash_key: &str) -> Result<Option<ThumbnailJob>> {
+        dao::query_job_by_hash_key(&self.pool, hash_key).await
+    }
+
+    /// 查询文件的任务状态, 执行中时附带进度
+    pub async fn status(&self, hash_key: &str) -> Result<Option<JobStatus>> {
+        let Some(job) = self.query(hash_key).await? else {
+            return Ok(None);
+        };
+        let progress = match job.state {
+            JobState::Running => self
+                .progress
+                .lock()
+                .ok()
+                .and_then(|progress| progress.get(hash_key).cloned()),
+            _ => None,
+        };
+        Ok(Some(JobStatus { job, progress }))
+    }
+
+    /// 启动worker, 并将上次异常退出时遗留的执行中任务重新排队、清理遗留的临时目录
+    pub async fn start(&self) -> Result<()> {
+        let swept = thumbnail::sweep_temp_dirs()?;
+        if swept > 0 {
+            info!("清理遗留的临时目录: {}个", swept);
+        }
+        let requeued = dao::requeue_running_jobs(&self.pool, now_millis()).await?;
+        if requeued > 0 {
+            info!("重新排队未完成的缩略图任务: {}个", requeued);
+        }
+        let mut handles = self
+            .handles
+            .lock()
+            .map_err(|_| anyhow::anyhow!("worker列表锁已损坏"))?;
+        for worker_id in 0..self.workers {
+            let queue = self.clone();
+            handles.push(tokio::spawn(
+                async move { queue.run_worker(worker_id).await },
+            ));
+        }
+        info!("缩略图任务worker已启动: {}个", self.workers);
+        Ok(())
+    }
+
+    /// 开始关闭: 不再领取新任务, 推送中的SSE连接随之结束
+    pub fn close(&self) {
+        self.shutdown.send_if_modified(|state| {
+            let modified = *state == Shutdown::None;
+            if modified {
+                *state = Shutdown::Draining;
+            }
+            modified
+        });
+        // 唤醒等待新任务的worker
+        self.notify.notify_waiters();
+    }
+
+    /// 是否已开始关闭
+    pub fn is_closed(&self) -> bool {
+        *self.shutdown.borrow() != Shutdown::None
+    }
+
+    /// 等待开始关闭, 返回的future不借用队列
+    pub fn closed(&self) -> impl Future<Output = ()> + use<> {
+        let mut shutdown = self.shutdown.subscribe();
+        async move {
+            let _ = shutdown.wait_for(|state| *state != Shutdown::None).await;
+        }
+    }
+
+    /// 关闭任务队列: 等待执行中的任务完成, 超过timeout时中断任务并重新排队,
+    /// 下次启动时继续执行(已完成的生成步骤不会重复执行)
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.close();
+        let handles = self
+            .handles
+            .lock()
+            .map(|mut handles| std::mem::take(&mut *handles))
+            .unwrap_or_default();
+        let mut workers = std::pin::pin!(futures::future::join_all(handles));
+        if tokio::time::timeout(timeout, workers.as_mut())
+            .await
+            .is_err()
+        {
+            warn!("等待执行中的任务超时({:?}), 中断并重新排队", timeout);
+            self.shutdown.send_replace(Shutdown::Aborting);
+            workers.await;
+        }
+        info!("缩略图任务worker已停止");
+    }
+
+    async fn run_worker(&self, worker_id: usize) {
+        let mut shutdown = self.shutdown.subscribe();
+        loop {
+            if self.is_closed() {
+                break;
+            }
+            let claimed = {
+                let _claim = self.claim.lock().await;
+                let job = match dao::claim_next_job(&self.pool, now_millis()).await {
+                    Ok(job) => job,
+                    Err(e) => {
+                        error!("worker{} 领取任务失败: {}", worker_id, e);
+                        None
+                    }
+                };
+                job.map(|job| {
+                    let cancel = Arc::new(Notify::new());
+                    if let Ok(mut running) = self.running.lock() {
+                        running.insert(job.hash_key.clone(), cancel.clone());
+                    }
+                    (job, cancel)
+                })
+            };
+            let Some((job, cancel)) = claimed else {
+                // 等待新任务或轮询到期的重试任务
+                let _ = tokio::time::timeout(POLL_INTERVAL, self.notify.notified()).await;
+                continue;
+            };
+            let start = std::time::Instant::now();
+            info!(
+                "worker{} 开始任务#{}(第{}次): {}",
+                worker_id, job.id, job.attempts, job.file_path
+            );
+            // 在单独的task中执行, 生成流程panic时只影响当前任务, worker继续领取后续任务
+            let mut task = {
+                let queue = self.clone();
+                let job = job.clone();
+                tokio::spawn(async move {
+                    let report = |event| queue.emit(&job.hash_key, event);
+                    process(&queue.pool, &job, &report).await
+                })
+            };
+            let outcome = tokio::select! {
+                result = &mut task => Outcome::Finished(result.unwrap_or_else(|e| {
+                    Err(anyhow::anyhow!("生成任务异常退出: {}", e))
+                })),
+                _ = cancel.notified() => Outcome::Cancelled,
+                _ = shutdown.wait_for(|state| *state == Shutdown::Aborting) => Outcome::Interrupted,
+            };
+            // 取消或中断时结束task并等待其退出, 正在执行的ffmpeg进程随之结束
+            if !task.is_finished() {
+                task.abort();
+                let _ = task.await;
+            }
+            if let Ok(mut running) = self.running.lock() {
+                running.remove(&job.hash_key);
+            }
+            let result = match outcome {
+                Outcome::Cancelled => {
+                    info!(
+                        "worker{} 任务#{}已取消: {}",
+                        worker_id, job.id, job.file_path
+                    );
+                    self.emit(
+                        &job.hash_key,
+                        ThumbnailEvent::Error {
+                            message: CANCELLED.to_string(),
+                            will_retry: false,
+                        },
+                    );
+                    self.cancel_running(&job).await
+                }
+                Outcome::Interrupted => {
+                    info!(
+                        "worker{} 任务#{}已中断, 下次启动时继续: {}",
+                        worker_id, job.id, job.file_path
+                    );
+                    self.emit(
+                        &job.hash_key,
+                        ThumbnailEvent::Error {
+                            message: INTERRUPTED.to_string(),
+                            will_retry: true,
+                        },
+                    );
+                    self.interrupt_running(&job).await
+                }
+                Outcome::Finished(Ok(frames)) => self.finish(&job, frames).await,
+                Outcome::Finished(Err(e)) => {
+                    // 超时的文件(如损坏的视频)重试通常也会超时, 没有关键帧的视频重试结果也不变, 直接标记为失败
+                    let next_run_at = if e.downcast_ref::<process::TimeoutError>().is_some()
+                        || e.downcast_ref::<thumbnail::NoKeyframesError>().is_some()
+                    {
+                        None
+                    } else {
+                        self.next_retry_at(job.attempts)
+                    };
+                    let retry = if next_run_at.is_some() {
+                        ", 稍后重试"
+                    } else {
+                        ""
+                    };
+                    warn!(
+                        "worker{} 任务#{}失败(第{}次){}: {}",
+                        worker_id, job.id, job.attempts, retry, e
+                    );
+                    self.emit(
+                        &job.hash_key,
+                        ThumbnailEvent::Error {
+                            message: e.to_string(),
+                            will_retry: next_run_at.is_some(),
+                        },
+                    );
+                    dao::fail_job(
+                        &self.pool,
+                        job.id,
+                        &e.to_string(),
+                        next_run_at,
+                        now_millis(),
+                    )
+                    .await
+                }
+            };
+            if let Err(e) = result {
+                error!("worker{} 更新任务#{}状态失败: {}", worker_id, job.id, e);
+            }
+            info!(
+                "worker{} 任务#{}结束, 耗时: {:?}",
+                worker_id,
+                job.id,
+                start.elapsed()
+            );
+        }
+        info!("worker{} 已停止", worker_id);
+    }
+
+    // 记录任务完成并发出done; 执行期间加入任务合并了新的预览方式时重新排队, 补充生成缺少的预览
+    async fn finish(&self, job: &ThumbnailJob, frames: u32) -> Result<()> {
+        if dao::finish_job(&self.pool, job.id, job.preview, now_millis()).await? {
+            self.emit(&job.hash_key, ThumbnailEvent::Done { frames });
+        } else {
+            info!(
+                "任务#{}执行期间预览方式已变化, 重新排队: {}",
+                job.id, job.file_path
+            );
+            self.notify.notify_one();
+        }
+        Ok(())
+    }
+
+    // 记录执行中的任务已取消, 生成了一半的产物标记为失败
+    async fn cancel_running(&self, job: &ThumbnailJob) -> Result<()> {
+        dao::fail_running_artifacts(&self.pool, &job.hash_key, CANCELLED).await?;
+        dao::cancel_job(
+            &self.pool,
+            &job.hash_key,
+            JobState::Running,
+            CANCELLED,
+            now_millis(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // 中断的任务重新排队(不计入执行次数), 生成了一半的产物标记为失败, 已完成的产物保留
+    async fn interrupt_running(&self, job: &ThumbnailJob) -> Result<()> {
+        dao::fail_running_artifacts(&self.pool, &job.hash_key, INTERRUPTED).await?;
+        dao::requeue_job(&self.pool, job.id, now_millis()).await
+    }
+
+    // 计算下次重试时间, 次数用尽时返回None
+    fn next_retry_at(&self, attempts: u32) -> Option<i64> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let backoff = self.retry_base * 2u32.saturating_pow(attempts.saturating_sub(1));
+        Some(now_millis() + backoff.as_millis() as i64)
+    }
+}
+
+/// 推送连接守卫, 见[JobQueue::watch]
+pub struct Watch {
+    queue: JobQueue,
+    hash_key: String,
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let Ok(mut watchers) = self.queue.watchers.lock() else {
+            return;
+        };
+        let Some(count) = watchers.get_mut(&self.hash_key) else {
+            return;
+        };
+        *count -= 1;
+        if *count > 0 {
+            return;
+        }
+        watchers.remove(&self.hash_key);
+        drop(watchers);
+        // 关闭服务时连接随之结束, 任务由关闭流程处理
+        if !self.queue.cancel_unwatched || self.queue.is_closed() {
+            return;
+        }
+        // 已结束的任务不受影响
+        let queue = self.queue.clone();
+        let hash_key = std::mem::take(&mut self.hash_key);
+        tokio::spawn(async move {
+            if let Err(e) = queue.cancel(&hash_key).await {
+                warn!("推送连接断开后取消任务失败 {}: {}", hash_key, e);
+            }
+        });
+    }
+}
+
+/// 执行任务: 入库视频信息并生成缩略图, 生成过程中的事件通过report发出, 返回关键帧数
+async fn process(
+    pool: &SqlitePool,
+    job: &ThumbnailJob,
+    report: &(dyn Fn(ThumbnailEvent) + Sync),
+) -> Result<u32> {
+    if dao::query_by_hash_key(pool, &job.hash_key).await?.is_none() {
+        dao::create_file_info(pool, &job.file_path).await?;
+    }
+    thumbnail::generate_thumbnails(pool, &job.hash_key, &job.file_path, job.preview, report).await
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_KEY: &str = "abc";
+    const FILE_PATH: &str = "/video/a.mp4";
+
+    async fn new_queue() -> JobQueue {
+        let pool = dao::connect_memory_pool().await;
+        JobQueue::new(pool, 1, 3, Duration::from_secs(30), false)
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_twice() {
+        let queue = new_queue().await;
+        let pool = &queue.pool;
+        // 排队中: 保持同一个任务, 预览方式合并
+        let job = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Gif, 1)
+            .await
+            .unwrap();
+        let again = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Clip, 2)
+            .await
+            .unwrap();
+        assert_eq!(again.id, job.id);
+        assert_eq!(again.state, JobState::Queued);
+        assert_eq!(again.preview, PreviewMode::Both);
+        assert_eq!(again.next_run_at, 1);
+
+        // 执行中: 状态和执行次数不变
+        let running = dao::claim_next_job(pool, 3).await.unwrap().unwrap();
+        assert_eq!(running.attempts, 1);
+        let again = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Both, 4)
+            .await
+            .unwrap();
+        assert_eq!(again.id, job.id);
+        assert_eq!(again.state, JobState::Running);
+        assert_eq!(again.attempts, 1);
+
+        // 已完成: 按新的预览方式重新排队
+        assert!(dao::finish_job(pool, job.id, PreviewMode::Both, 5)
+            .await
+            .unwrap());
+        let again = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Clip, 6)
+            .await
+            .unwrap();
+        assert_eq!(again.id, job.id);
+        assert_eq!(again.state, JobState::Queued);
+        assert_eq!(again.preview, PreviewMode::Clip);
+        assert_eq!(again.attempts, 0);
+        assert_eq!(again.next_run_at, 6);
+
+        // 已失败: 重新排队并清除错误和执行次数
+        dao::claim_next_job(pool, 7).await.unwrap().unwrap();
+        dao::fail_job(pool, job.id, "ffmpeg失败", None, 8)
+            .await
+            .unwrap();
+        let failed = queue.query(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(failed.state, JobState::Failed);
+        assert_eq!(failed.last_error.as_deref(), Some("ffmpeg失败"));
+        let again = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Gif, 9)
+            .await
+            .unwrap();
+        assert_eq!(again.id, job.id);
+        assert_eq!(again.state, JobState::Queued);
+        assert_eq!(again.preview, PreviewMode::Gif);
+        assert_eq!(again.attempts, 0);
+        assert_eq!(again.last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_finish_with_changed_preview() {
+        let queue = new_queue().await;
+        let pool = &queue.pool;
+        dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Gif, 1)
+            .await
+            .unwrap();
+        let running = dao::claim_next_job(pool, 2).await.unwrap().unwrap();
+        assert_eq!(running.preview, PreviewMode::Gif);
+        // 执行中请求短视频预览, 合并为both
+        let merged = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Clip, 3)
+            .await
+            .unwrap();
+        assert_eq!(merged.state, JobState::Running);
+        assert_eq!(merged.preview, PreviewMode::Both);
+        // 按gif执行完成后重新排队
+        assert!(!dao::finish_job(pool, running.id, running.preview, 4)
+            .await
+            .unwrap());
+        let requeued = queue.query(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(requeued.state, JobState::Queued);
+        assert_eq!(requeued.attempts, 0);
+        assert_eq!(requeued.next_run_at, 4);
+        let running = dao::claim_next_job(pool, 4).await.unwrap().unwrap();
+        assert_eq!(running.preview, PreviewMode::Both);
+        assert!(dao::finish_job(pool, running.id, running.preview, 5)
+            .await
+            .unwrap());
+        let done = queue.query(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(done.state, JobState::Done);
+    }
+
+    #[tokio::test]
+    async fn test_retry_schedule() {
+        let queue = new_queue().await;
+        let pool = &queue.pool;
+        // 第n次失败后等待 30秒 * 2^(n-1), 执行3次后不再重试
+        for (attempts, backoff) in [(1, Some(30_000)), (2, Some(60_000)), (3, None), (4, None)] {
+            let before = now_millis();
+            let next_run_at = queue.next_retry_at(attempts);
+            let after = now_millis();
+            assert_eq!(
+                next_run_at.is_some(),
+                backoff.is_some(),
+                "attempts={}",
+                attempts
+            );
+            if let (Some(at), Some(backoff)) = (next_run_at, backoff) {
+                assert!(
+                    (before + backoff..=after + backoff).contains(&at),
+                    "attempts={}",
+                    attempts
+                );
+            }
+        }
+
+        // 等待重试的任务到期前不会被领取, 每次领取执行次数加1
+        let job = dao::enqueue_job(pool, HASH_KEY, FILE_PATH, PreviewMode::Gif, 0)
+            .await
+            .unwrap();
+        let mut now = 0;
+        for attempts in 1..=2 {
+            let claimed = dao::claim_next_job(pool, now).await.unwrap().unwrap();
+            assert_eq!(claimed.attempts, attempts);
+            dao::fail_job(pool, job.id, "ffmpeg失败", Some(now + 1_000), now)
+                .await
+                .unwrap();
+            let queued = queue.query(HASH_KEY).await.unwrap().unwrap();
+            assert_eq!(queued.state, JobState::Queued);
+            assert_eq!(queued.last_error.as_deref(), Some("ffmpeg失败"));
+            assert!(dao::claim_next_job(pool, now + 999)
+                .await
+                .unwrap()
+                .is_none());
+            now += 1_000;
+        }
+        let claimed = dao::claim_next_job(pool, now).await.unwrap().unwrap();
+        assert_eq!(claimed.attempts, 3);
+        dao::fail_job(pool, job.id, "ffmpeg失败", None, now)
+            .await
+            .unwrap();
+        let failed = queue.query(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(failed.state, JobState::Failed);
+        assert!(dao::claim_next_job(pool, i64::MAX).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued() {
+        let queue = new_queue().await;
+        assert!(queue.cancel(HASH_KEY).await.unwrap().is_none());
+        let mut events = queue.subscribe();
+        queue
+            .enqueue(HASH_KEY, FILE_PATH, PreviewMode::Gif)
+            .await
+            .unwrap();
+        let job = queue.cancel(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(job.state, JobState::Cancelled);
+        assert_eq!(job.last_error.as_deref(), Some(CANCELLED));
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.hash_key, HASH_KEY);
+        assert!(matches!(
+            event.event,
+            ThumbnailEvent::Error {
+                will_retry: false,
+                ..
+            }
+        ));
+        // 已取消的任务不会被领取, 重新加入后恢复排队
+        assert!(dao::claim_next_job(&queue.pool, i64::MAX)
+            .await
+            .unwrap()
+            .is_none());
+        let job = queue
+            .enqueue(HASH_KEY, FILE_PATH, PreviewMode::Gif)
+            .await
+            .unwrap();
+        assert_eq!(job.state, JobState::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running() {
+        let queue = new_queue().await;
+        queue
+            .enqueue(HASH_KEY, FILE_PATH, PreviewMode::Gif)
+            .await
+            .unwrap();
+        // 模拟worker领取任务并登记取消通知
+        let job = dao::claim_next_job(&queue.pool, now_millis())
+            .await
+            .unwrap()
+            .unwrap();
+        let cancel = Arc::new(Notify::new());
+        queue
+            .running
+            .lock()
+            .unwrap()
+            .insert(HASH_KEY.to_string(), cancel.clone());
+        // 执行中的任务只通知worker中断, 由worker记录取消
+        let running = queue.cancel(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(running.state, JobState::Running);
+        tokio::time::timeout(Duration::from_secs(1), cancel.notified())
+            .await
+            .expect("worker未收到取消通知");
+        queue.running.lock().unwrap().remove(HASH_KEY);
+        queue.cancel_running(&job).await.unwrap();
+        let cancelled = queue.query(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(cancelled.state, JobState::Cancelled);
+        assert_eq!(cancelled.last_error.as_deref(), Some(CANCELLED));
+        // 已结束的任务不受影响
+        dao::enqueue_job(&queue.pool, HASH_KEY, FILE_PATH, PreviewMode::Gif, 0)
+            .await
+            .unwrap();
+        let job = dao::claim_next_job(&queue.pool, now_millis())
+            .await
+            .unwrap()
+            .unwrap();
+        dao::finish_job(&queue.pool, job.id, job.preview, now_millis())
+            .await
+            .unwrap();
+        let done = queue.cancel(HASH_KEY).await.unwrap().unwrap();
+        assert_eq!(done.state, JobState::Done);
+    }
+}
@@ -2,10 +2,17 @@ pub mod model;
 
 pub mod dao;
 
+pub mod migrate;
+
 pub mod fhash;
 
+#[cfg(windows)]
 pub mod es;
 
+pub mod search;
+
+pub mod local_index;
+
 pub mod init;
 
 pub mod handler;
@@ -13,3 +20,15 @@ pub mod handler;
 pub mod errors;
 
 pub mod thumbnail;
+
+pub mod probe;
+
+pub mod hwaccel;
+
+pub mod state;
+
+pub mod job;
+
+pub mod serve;
+
+pub mod process;
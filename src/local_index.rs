@@ -0,0 +1,322 @@
+use crate::dao;
+use crate::errors::IError;
+use crate::model::FileIndex;
+use crate::search::{FileSearcher, SdkFileItem, SearchRequest, SearchResult, SEARCH_DEFAULTS};
+use anyhow::Result;
+use async_walkdir::WalkDir;
+use dotenvy::var;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_stream::StreamExt;
+use tracing::{error, info, warn};
+
+/// 1601-01-01到1970-01-01之间的100纳秒数(FILETIME偏移)
+const FILETIME_UNIX_OFFSET: i64 = 116_444_736_000_000_000;
+
+/// 扫描时每批写入索引的文件数, 每批在一个事务中提交
+const INDEX_BATCH_SIZE: usize = 500;
+
+/// 本地索引搜索后端: 遍历配置的媒体库目录并索引到sqlite, 跨平台可用
+pub struct LocalIndexSearcher {
+    pool: SqlitePool,
+    // 媒体库根目录
+    roots: Vec<PathBuf>,
+    // 索引的扩展名(不带`.`), 为空时索引所有文件
+    extensions: Vec<String>,
+    // 重新扫描间隔, 为0时只在启动时扫描一次
+    rescan_interval: Duration,
+    // 是否已完成首次扫描
+    ready: Arc<AtomicBool>,
+}
+
+impl LocalIndexSearcher {
+    pub fn new(
+        pool: SqlitePool,
+        roots: Vec<PathBuf>,
+        extensions: Vec<String>,
+        rescan_interval: Duration,
+    ) -> Self {
+        Self {
+            pool,
+            roots,
+            extensions,
+            rescan_interval,
+            ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// 从环境变量创建
+    /// `LIBRARY_ROOTS`: 媒体库根目录, 多个目录按系统PATH分隔符分隔(windows为`;`, 其他为`:`)
+    /// `LIBRARY_RESCAN_SECS`: 重新扫描间隔(秒), 默认600
+    /// 只索引`SEARCH_EXTENSIONS`中的扩展名(默认为视频扩展名), 为空时索引所有文件
+    pub fn from_env(pool: SqlitePool) -> Self {
+        let roots = var("LIBRARY_ROOTS")
+            .map(|roots| std::env::split_paths(&roots).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if roots.is_empty() {
+            warn!("未配置LIBRARY_ROOTS, 本地索引为空");
+        }
+        let rescan_secs = var("LIBRARY_RESCAN_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(600);
+        let searcher = Self::new(
+            pool,
+            roots,
+            SEARCH_DEFAULTS.extensions.clone(),
+            Duration::from_secs(rescan_secs),
+        );
+        searcher.spawn_indexer();
+        searcher
+    }
+
+    /// 首次扫描是否已完成
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// 启动后台索引任务
+    pub fn spawn_indexer(&self) {
+        let pool = self.pool.clone();
+        let roots = self.roots.clone();
+        let extensions = self.extensions.clone();
+        let rescan_interval = self.rescan_interval;
+        let ready = self.ready.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = index_roots(&pool, &roots, &extensions).await {
+                    error!("本地索引扫描失败: {}", e);
+                }
+                ready.store(true, Ordering::Release);
+                if rescan_interval.is_zero() {
+                    break;
+                }
+                tokio::time::sleep(rescan_interval).await;
+            }
+        });
+    }
+
+    async fn search_index(&self, req: &SearchRequest) -> Result<SearchResult, IError> {
+        if self.roots.is_empty() {
+            return Err(IError::SearchUnavailable("未配置LIBRARY_ROOTS".to_string()));
+        }
+        let start = std::time::Instant::now();
+        let rows = dao::search_file_index(&self.pool, req).await?;
+        let total = dao::count_file_index(&self.pool, req).await?;
+        let list = rows.into_iter().map(SdkFileItem::from).collect::<Vec<_>>();
+        // 首次扫描未完成时, 空结果可能只是还没索引到
+        if list.is_empty() && !self.is_ready() {
+            return Err(IError::IndexLoading);
+        }
+        let query = req.query_string();
+        info!(
+            "本地索引查询【{}】({}/{})耗时: {:?}",
+            query,
+            list.len(),
+            total,
+            start.elapsed()
+        );
+        Ok(SearchResult {
+            query,
+            total,
+            items: list,
+        })
+    }
+}
+
+impl FileSearcher for LocalIndexSearcher {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn search<'a>(&'a self, req: &'a SearchRequest) -> BoxFuture<'a, Result<SearchResult, IError>> {
+        self.search_index(req).boxed()
+    }
+}
+
+/// 扫描所有根目录, 写入新增或变化的文件并清理已不存在的文件
+async fn index_roots(pool: &SqlitePool, roots: &[PathBuf], extensions: &[String]) -> Result<()> {
+    let start = std::time::Instant::now();
+    let scanned_at = unix_millis(SystemTime::now());
+    // 扫描结束时仍未出现的文件即为已删除的文件
+    let mut stale = dao::query_file_index_stamps(pool).await?;
+    let mut batch = Vec::with_capacity(INDEX_BATCH_SIZE);
+    let mut count = 0;
+    let mut changed = 0;
+    for root in roots {
+        info!("扫描媒体库: {}", root.display());
+        let mut entries = WalkDir::new(root);
+        while let Some(entry) = entries.next().await {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("读取目录失败: {}", e);
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if !has_extension(&path, extensions) {
+                continue;
+            }
+            let metadata = match tokio::fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            let Some(file_index) = build_file_index(&path, &metadata, scanned_at) else {
+                continue;
+            };
+            count += 1;
+            let stamp = (file_index.file_size, file_index.date_created);
+            if stale.remove(&file_index.file_path) == Some(stamp) {
+                continue;
+            }
+            batch.push(file_index);
+            if batch.len() >= INDEX_BATCH_SIZE {
+                dao::upsert_file_indexes(pool, &batch).await?;
+                changed += batch.len();
+                batch.clear();
+            }
+        }
+    }
+    dao::upsert_file_indexes(pool, &batch).await?;
+    changed += batch.len();
+    let stale = stale.into_keys().collect::<Vec<_>>();
+    let removed = dao::delete_file_indexes(pool, &stale).await?;
+    info!(
+        "本地索引扫描完成: {}个文件, 更新{}个, 移除{}个, 耗时: {:?}",
+        count,
+        changed,
+        removed,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+// 文件名是否以指定的扩展名之一结尾(不区分大小写, 与搜索时的匹配方式一致), 为空时不限制
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+    let Some(file_name) = path.file_name() else {
+        return false;
+    };
+    let file_name = file_name.to_string_lossy().to_lowercase();
+    extensions
+        .iter()
+        .any(|ext| file_name.ends_with(&format!(".{}", ext.to_lowercase())))
+}
+
+fn build_file_index(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+    scanned_at: i64,
+) -> Option<FileIndex> {
+    let file_path = path.to_str()?.to_string();
+    let file_name = path.file_name()?.to_str()?.to_string();
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let dir_path = path.parent()?.to_str()?.to_string();
+    // 部分文件系统不支持创建时间, 使用修改时间代替
+    let created = metadata.created().or_else(|_| metadata.modified()).ok()?;
+    Some(FileIndex {
+        id: 0,
+        file_path,
+        file_name,
+        ext,
+        dir_path,
+        file_size: metadata.len() as i64,
+        date_created: to_filetime(created),
+        scanned_at,
+    })
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// 转换为windows FILETIME(与everything返回的创建时间一致)
+fn to_filetime(time: SystemTime) -> i64 {
+    let nanos = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default();
+    nanos / 100 + FILETIME_UNIX_OFFSET
+}
+
+impl From<FileIndex> for SdkFileItem {
+    fn from(fi: FileIndex) -> Self {
+        SdkFileItem {
+            index: fi.id as u32,
+            filepath: fi.file_path,
+            filename: fi.file_name,
+            ext: fi.ext,
+            path: fi.dir_path,
+            size: fi.file_size as u64,
+            date_created: fi.date_created.to_string(),
+            is_dir: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn query_scanned_at(pool: &SqlitePool, file_path: &Path) -> Option<i64> {
+        sqlx::query_as::<_, (i64,)>("SELECT scanned_at FROM file_index WHERE file_path = ?")
+            .bind(file_path.to_str().unwrap())
+            .fetch_optional(pool)
+            .await
+            .unwrap()
+            .map(|(scanned_at,)| scanned_at)
+    }
+
+    #[tokio::test]
+    async fn test_index_roots() {
+        let pool = dao::connect_memory_pool().await;
+        let root = std::env::temp_dir().join(format!("videoinfo-index-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        let kept = root.join("kept.mp4");
+        let changed = root.join("sub").join("changed.MKV");
+        let removed = root.join("removed.mp4");
+        let ignored = root.join("ignored.txt");
+        for path in [&kept, &changed, &removed, &ignored] {
+            std::fs::write(path, b"video").unwrap();
+        }
+        let roots = vec![root.clone()];
+        let extensions = vec!["mp4".to_string(), "mkv".to_string()];
+
+        index_roots(&pool, &roots, &extensions).await.unwrap();
+        let stamps = dao::query_file_index_stamps(&pool).await.unwrap();
+        assert_eq!(stamps.len(), 3, "{:?}", stamps);
+        assert!(query_scanned_at(&pool, &ignored).await.is_none());
+        let kept_at = query_scanned_at(&pool, &kept).await.unwrap();
+        let changed_at = query_scanned_at(&pool, &changed).await.unwrap();
+
+        // 保证第二次扫描的时间戳不同
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        std::fs::write(&changed, b"longer video").unwrap();
+        std::fs::remove_file(&removed).unwrap();
+        index_roots(&pool, &roots, &extensions).await.unwrap();
+        let stamps = dao::query_file_index_stamps(&pool).await.unwrap();
+        assert_eq!(stamps.len(), 2, "{:?}", stamps);
+        // 未变化的文件不重写, 变化的文件更新大小
+        assert_eq!(query_scanned_at(&pool, &kept).await, Some(kept_at));
+        assert!(query_scanned_at(&pool, &changed).await.unwrap() > changed_at);
+        assert_eq!(stamps[changed.to_str().unwrap()].0, 12);
+        assert!(query_scanned_at(&pool, &removed).await.is_none());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,21 +1,54 @@
 use anyhow::Result;
-use axum::Router;
 use axum::routing::get;
+use axum::Router;
 use dotenvy::var;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
-use tracing::info;
-use videoinfo::{dao, handler, init};
+use tracing::{info, warn};
+use videoinfo::job::JobQueue;
+use videoinfo::state::AppState;
+use videoinfo::{dao, handler, init, migrate, search};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     init::log();
     // 连接数据库
     let pool = dao::connect_pool().await?;
+    // 命令: migrate 只执行数据库迁移后退出
+    if let Some("migrate") = std::env::args().nth(1).as_deref() {
+        let applied = migrate::run(&pool).await?;
+        info!("迁移完成, 本次执行{}个", applied);
+        return Ok(());
+    }
+    // 启动时执行数据库迁移
+    migrate::run(&pool).await?;
+    // 搜索后端
+    let searcher = search::from_env(pool.clone())?;
+    // 缩略图任务队列
+    let jobs = JobQueue::from_env(pool.clone());
+    jobs.start().await?;
+    let state = AppState {
+        pool: pool.clone(),
+        searcher,
+        jobs: jobs.clone(),
+    };
     let app = Router::new()
+        .route("/search", get(handler::search))
         .route("/thumbnails", get(handler::get_thumbnails))
         .route("/sse", get(handler::sse_handler))
-        .with_state(pool)
+        .route("/videos/{id}", get(handler::get_video_by_id))
+        .route("/files/{hash}/video", get(handler::get_video_by_hash))
+        .route("/files/{hash}/keyframes", get(handler::get_keyframes))
+        .route(
+            "/files/{hash}/job",
+            get(handler::get_job_status).delete(handler::cancel_job),
+        )
+        .route(
+            "/files/{hash}/thumbnails/{name}",
+            get(handler::get_thumbnail_file),
+        )
+        .with_state(state)
         // 配置CORS
         .layer(
             CorsLayer::new()
@@ -27,6 +60,45 @@ async fn main() -> Result<()> {
     let server = var("SERVER").unwrap_or("0.0.0.0:3000".to_string());
     let listener = TcpListener::bind(&server).await?;
     info!("服务启动在 http://{server}");
-    axum::serve(listener, app).await?;
-    Ok(())
+    // 收到退出信号后停止接收请求, SSE推送随任务队列关闭而结束
+    let signal = {
+        let jobs = jobs.clone();
+        async move {
+            init::shutdown_signal().await;
+            info!("开始关闭服务");
+            jobs.close();
+        }
+    };
+    let mut serve = std::pin::pin!(axum::serve(listener, app)
+        .with_graceful_shutdown(signal)
+        .into_future());
+    let shutdown_timeout = Duration::from_secs(
+        var("SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30),
+    );
+    // 退出信号和服务结束可能同时就绪, 优先处理退出信号; 无论哪个分支都要等待任务队列关闭
+    let result = tokio::select! {
+        biased;
+        _ = jobs.closed() => {
+            // 等待连接关闭和执行中的任务完成, 都最多等待shutdown_timeout
+            let (result, ()) = tokio::join!(
+                tokio::time::timeout(shutdown_timeout, &mut serve),
+                jobs.shutdown(shutdown_timeout)
+            );
+            result.unwrap_or_else(|_| {
+                warn!("等待连接关闭超时({:?}), 强制关闭", shutdown_timeout);
+                Ok(())
+            })
+        }
+        // 服务异常结束(未收到退出信号)时也要停止任务队列
+        result = &mut serve => {
+            jobs.shutdown(shutdown_timeout).await;
+            result
+        }
+    };
+    pool.close().await;
+    info!("服务已关闭");
+    Ok(result?)
 }
@@ -0,0 +1,257 @@
+use crate::{dao, thumbnail};
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use sqlx::SqlitePool;
+use tracing::info;
+
+/// 数据库迁移
+pub struct Migration {
+    // 版本号, 按升序执行
+    pub version: i64,
+    // 描述
+    pub description: &'static str,
+    pub kind: MigrationKind,
+}
+
+pub enum MigrationKind {
+    /// 执行sql脚本(可包含多条语句)
+    Sql(&'static str),
+    /// 执行代码(如需要读取磁盘文件的数据修复)
+    Code(fn(&SqlitePool) -> BoxFuture<'_, Result<()>>),
+}
+
+/// 所有迁移, 新增迁移只能追加到末尾, 已发布的迁移不能修改
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create file_info",
+        kind: MigrationKind::Sql(include_str!("../migrations/0001_create_file_info.sql")),
+    },
+    Migration {
+        version: 2,
+        description: "create file_index",
+        kind: MigrationKind::Sql(include_str!("../migrations/0002_create_file_index.sql")),
+    },
+    Migration {
+        version: 3,
+        description: "recompute 64-bit file_size",
+        kind: MigrationKind::Code(|pool| dao::recompute_file_sizes(pool).boxed()),
+    },
+    Migration {
+        version: 4,
+        description: "create media_info and media_stream",
+        kind: MigrationKind::Sql(include_str!("../migrations/0004_create_media_info.sql")),
+    },
+    Migration {
+        version: 5,
+        description: "create thumbnail_job",
+        kind: MigrationKind::Sql(include_str!("../migrations/0005_create_thumbnail_job.sql")),
+    },
+    Migration {
+        version: 6,
+        description: "create artifact",
+        kind: MigrationKind::Sql(include_str!("../migrations/0006_create_artifact.sql")),
+    },
+    Migration {
+        version: 7,
+        description: "move filename-keyed thumbnail dirs to hash-keyed dirs",
+        kind: MigrationKind::Code(|pool| thumbnail::migrate_filename_dirs(pool).boxed()),
+    },
+    Migration {
+        version: 8,
+        description: "create keyframe",
+        kind: MigrationKind::Sql(include_str!("../migrations/0008_create_keyframe.sql")),
+    },
+    Migration {
+        version: 9,
+        description: "add thumbnail_job preview mode",
+        kind: MigrationKind::Sql(include_str!("../migrations/0009_job_preview.sql")),
+    },
+    Migration {
+        version: 10,
+        description: "track artifact generation state",
+        kind: MigrationKind::Sql(include_str!("../migrations/0010_artifact_state.sql")),
+    },
+];
+
+/// 当前数据库的版本号, 未执行过迁移时为0
+pub async fn current_version(pool: &SqlitePool) -> Result<i64> {
+    ensure_version_table(pool).await?;
+    let (version,) =
+        sqlx::query_as::<_, (i64,)>("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+            .fetch_one(pool)
+            .await?;
+    Ok(version)
+}
+
+/// 执行所有未执行的迁移, 返回本次执行的数量
+pub async fn run(pool: &SqlitePool) -> Result<usize> {
+    let current = current_version(pool).await?;
+    let mut applied = 0;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let start = std::time::Instant::now();
+        match migration.kind {
+            MigrationKind::Sql(sql) => {
+                // sql迁移与版本记录在同一事务中
+                let mut tx = pool.begin().await?;
+                sqlx::raw_sql(sql).execute(&mut *tx).await?;
+                record_version(&mut tx, migration).await?;
+                tx.commit().await?;
+            }
+            MigrationKind::Code(f) => {
+                f(pool).await?;
+                let mut tx = pool.begin().await?;
+                record_version(&mut tx, migration).await?;
+                tx.commit().await?;
+            }
+        }
+        info!(
+            "执行迁移 v{} {} 耗时: {:?}",
+            migration.version,
+            migration.description,
+            start.elapsed()
+        );
+        applied += 1;
+    }
+    info!("数据库版本: v{}", current_version(pool).await?);
+    Ok(applied)
+}
+
+async fn ensure_version_table(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )"#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn record_version(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    migration: &Migration,
+) -> Result<()> {
+    sqlx::query("INSERT INTO schema_version (version, description) VALUES (?, ?)")
+        .bind(migration.version)
+        .bind(migration.description)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // 未执行迁移的内存数据库, 只使用一个连接
+    async fn connect_empty_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn table_names(pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_as::<_, (String,)>(
+            "SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(name,)| name)
+        .collect()
+    }
+
+    fn latest_version() -> i64 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    #[test]
+    fn test_versions_ascending() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(
+                pair[0].version < pair[1].version,
+                "v{} {} 应在 v{} {} 之前",
+                pair[0].version,
+                pair[0].description,
+                pair[1].version,
+                pair[1].description
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_fresh() {
+        let pool = connect_empty_pool().await;
+        assert_eq!(current_version(&pool).await.unwrap(), 0);
+        assert_eq!(run(&pool).await.unwrap(), MIGRATIONS.len());
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+        let tables = table_names(&pool).await;
+        for table in [
+            "artifact",
+            "file_index",
+            "file_info",
+            "keyframe",
+            "media_info",
+            "media_stream",
+            "schema_version",
+            "thumbnail_job",
+        ] {
+            assert!(
+                tables.iter().any(|t| t == table),
+                "缺少表{}: {:?}",
+                table,
+                tables
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_twice() {
+        let pool = connect_empty_pool().await;
+        assert_eq!(run(&pool).await.unwrap(), MIGRATIONS.len());
+        assert_eq!(run(&pool).await.unwrap(), 0);
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+        let (count,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM schema_version")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn test_run_baseline() {
+        // 引入迁移前的数据库: 只有启动时创建的file_info, 没有schema_version
+        let pool = connect_empty_pool().await;
+        sqlx::raw_sql(
+            r#"CREATE TABLE IF NOT EXISTS file_info (
+                    id INTEGER PRIMARY KEY,
+                    hash_key TEXT NOT NULL,
+                    total_frame INTEGER NOT NULL,
+                    file_path TEXT NOT NULL,
+                    file_size INTEGER NOT NULL,
+                    UNIQUE(hash_key)
+                );
+                INSERT INTO file_info (hash_key, total_frame, file_path, file_size)
+                    VALUES ('abc', 100, '/videoinfo-missing/a.mp4', 1024);"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert_eq!(run(&pool).await.unwrap(), MIGRATIONS.len());
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+        // 已有数据保留, 文件不存在时不重新计算大小
+        let file_info = dao::query_by_hash_key(&pool, "abc").await.unwrap().unwrap();
+        assert_eq!(file_info.file_path, "/videoinfo-missing/a.mp4");
+        assert_eq!(file_info.file_size, 1024);
+    }
+}
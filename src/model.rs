@@ -1,7 +1,9 @@
 use crate::fhash;
+use crate::search::{SdkFileItem, SortOrder};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(Serialize, Deserialize, Debug, sqlx::FromRow)]
 pub struct FileInfo {
@@ -9,7 +11,7 @@ pub struct FileInfo {
     pub hash_key: String,
     pub total_frame: u32,
     pub file_path: String,
-    pub file_size: u32,
+    pub file_size: u64,
 }
 
 impl FileInfo {
@@ -18,7 +20,7 @@ impl FileInfo {
         hash_key: String,
         total_frame: u32,
         file_path: String,
-        file_size: u32,
+        file_size: u64,
     ) -> Self {
         Self {
             id,
@@ -37,16 +39,15 @@ impl FileInfo {
             .to_str()
             .unwrap();
         // 去除后缀  a.a.a.mp4 -> a.a.a
-        let filename = filename
+        filename
             .split('.')
             .take(filename.split('.').count() - 1)
             .collect::<Vec<&str>>()
-            .join(".");
-        filename
+            .join(".")
     }
 
     pub fn from_path(path: &str) -> Self {
-        let file_size = std::fs::metadata(path).map(|m| m.len() as u32).unwrap_or(0);
+        let file_size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
         Self {
             id: 0,
             hash_key: fhash::compute_sample_hash(path).unwrap_or_default(),
@@ -57,10 +58,393 @@ impl FileInfo {
     }
 }
 
+/// 视频容器信息
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaInfo {
+    pub file_id: u32,
+    // 时长(秒)
+    pub duration: Option<f64>,
+    // 容器格式, 如: matroska,webm
+    pub container: String,
+    // 总码率(bit/s)
+    pub bit_rate: Option<i64>,
+}
+
+/// 视频/音频/字幕流信息
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaStream {
+    pub id: u32,
+    pub file_id: u32,
+    pub stream_index: u32,
+    // video|audio|subtitle|data|attachment
+    pub codec_type: String,
+    pub codec_name: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    pub pix_fmt: Option<String>,
+    // 音频声道数
+    pub channels: Option<u32>,
+    pub nb_frames: Option<u32>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+}
+
+/// 生成产物类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    // 关键帧图片目录
+    Keyframes,
+    // 预览动图(gif或动态webp)
+    Gif,
+    // 雪碧图及WebVTT缩略图轨道
+    Sprite,
+    // 联系表
+    ContactSheet,
+    // 短视频预览片段
+    Teaser,
+}
+
+/// 生成产物状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ArtifactState {
+    // 生成中(或上次生成被中断)
+    Running,
+    // 已完成
+    Done,
+    // 生成失败
+    Failed,
+}
+
+/// 生成产物记录
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Artifact {
+    pub id: i64,
+    pub hash_key: String,
+    pub kind: ArtifactKind,
+    pub path: String,
+    pub created_at: i64,
+    pub state: ArtifactState,
+    // 生成参数, 参数变化时需要重新生成
+    pub params: Option<String>,
+    // 帧数(关键帧/雪碧图/联系表的图片数, 短视频的片段数)
+    pub frame_count: Option<u32>,
+    pub error: Option<String>,
+    // 完成时间(毫秒时间戳)
+    pub completed_at: Option<i64>,
+}
+
+/// 关键帧图片及其在视频中的时间点
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Keyframe {
+    pub hash_key: String,
+    // 图片文件名, 如: 0001.png, 0001.webp
+    pub name: String,
+    // 显示时间戳(秒)
+    pub pts_time: f64,
+}
+
+/// 缩略图任务状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum JobState {
+    // 排队中(包括等待重试)
+    Queued,
+    // 执行中
+    Running,
+    // 已完成
+    Done,
+    // 重试次数用尽后失败
+    Failed,
+    // 已取消
+    Cancelled,
+}
+
+/// 缩略图生成任务
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailJob {
+    pub id: i64,
+    pub hash_key: String,
+    pub file_path: String,
+    pub state: JobState,
+    // 需要生成的预览方式
+    pub preview: PreviewMode,
+    // 已执行次数
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    // 下次可执行时间(毫秒时间戳)
+    pub next_run_at: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// 缩略图生成事件, 由生成流程发出并通过SSE推送, 事件名为type, 数据为json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ThumbnailEvent {
+    // 已加入任务队列
+    Queued {
+        job_id: i64,
+        state: JobState,
+    },
+    // 生成进度
+    Progress(JobProgress),
+    // 关键帧, index从0开始, total为关键帧总数
+    Frame {
+        index: u32,
+        total: u32,
+        url: String,
+        pts_time: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<String>,
+    },
+    // 预览动图
+    Gif {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<String>,
+    },
+    // 短视频预览片段
+    Clip {
+        url: String,
+    },
+    // 全部生成完成
+    Done {
+        frames: u32,
+    },
+    // 生成失败, will_retry为true时任务稍后重试
+    Error {
+        message: String,
+        will_retry: bool,
+    },
+}
+
+impl ThumbnailEvent {
+    /// SSE事件名
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThumbnailEvent::Queued { .. } => "queued",
+            ThumbnailEvent::Progress(_) => "progress",
+            ThumbnailEvent::Frame { .. } => "frame",
+            ThumbnailEvent::Gif { .. } => "gif",
+            ThumbnailEvent::Clip { .. } => "clip",
+            ThumbnailEvent::Done { .. } => "done",
+            ThumbnailEvent::Error { .. } => "error",
+        }
+    }
+
+    /// 是否为最后一个事件(完成或不再重试的失败)
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ThumbnailEvent::Done { .. }
+                | ThumbnailEvent::Error {
+                    will_retry: false,
+                    ..
+                }
+        )
+    }
+}
+
+/// 缩略图任务的生成进度
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    // 当前步骤
+    pub stage: ArtifactKind,
+    // 整体进度(0~100), 各步骤按预计耗时加权
+    pub percent: f64,
+    // 当前步骤的进度(0~100)
+    pub stage_percent: f64,
+    // 当前步骤的预计剩余时间(秒)
+    pub stage_eta_secs: Option<f64>,
+    // 整个任务的预计剩余时间(秒), 按当前步骤的速度估算剩余步骤
+    pub eta_secs: Option<f64>,
+}
+
+/// 缩略图任务状态及执行中的进度
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub job: ThumbnailJob,
+    // 执行中时返回
+    pub progress: Option<JobProgress>,
+}
+
+/// 附带所属文件的缩略图事件, 用于同时推送多个文件
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEvent {
+    pub hash_key: String,
+    pub file_path: String,
+    #[serde(flatten)]
+    pub event: ThumbnailEvent,
+}
+
+/// 本地索引中的文件记录
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow)]
+pub struct FileIndex {
+    pub id: i64,
+    pub file_path: String,
+    pub file_name: String,
+    pub ext: String,
+    pub dir_path: String,
+    pub file_size: i64,
+    // 创建时间(FILETIME)
+    pub date_created: i64,
+    // 最后一次扫描时间(毫秒时间戳)
+    pub scanned_at: i64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct CodeRequest {
     pub code: String,
+    // 以下为可选的搜索条件, 未传时使用配置的默认值
+    // 最小文件大小(字节), 0表示不限制
+    pub min_size: Option<u64>,
+    // 最大文件大小(字节), 0表示不限制
+    pub max_size: Option<u64>,
+    // 扩展名, 逗号分隔
+    pub exts: Option<String>,
+    // 最大结果数(每页数量)
+    #[serde(alias = "limit")]
+    pub max_results: Option<u32>,
+    // 分页偏移
+    pub offset: Option<u32>,
+    // 排序方式
+    pub sort: Option<SortOrder>,
+    // 是否区分大小写
+    pub match_case: Option<bool>,
+    // 只搜索这些目录, 分号分隔
+    pub include_paths: Option<String>,
+    // 排除这些目录, 分号分隔
+    pub exclude_paths: Option<String>,
+    // 缩略图是否同时返回base64数据, 默认只返回url(短视频片段不支持)
+    pub base64: Option<bool>,
+    // 预览方式, 未传时使用配置的默认值
+    pub preview: Option<PreviewMode>,
+}
+
+/// 预览方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum PreviewMode {
+    // gif动图
+    #[default]
+    Gif,
+    // 短视频片段(webm/mp4)
+    Clip,
+    // gif和短视频片段
+    Both,
+}
+
+impl PreviewMode {
+    pub fn includes_gif(&self) -> bool {
+        matches!(self, PreviewMode::Gif | PreviewMode::Both)
+    }
+
+    pub fn includes_clip(&self) -> bool {
+        matches!(self, PreviewMode::Clip | PreviewMode::Both)
+    }
+
+    /// 合并两种预览方式, 需要生成两者包含的所有预览
+    pub fn merge(self, other: PreviewMode) -> PreviewMode {
+        if self == other {
+            self
+        } else {
+            PreviewMode::Both
+        }
+    }
+}
+
+impl FromStr for PreviewMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s.to_lowercase().as_str() {
+            "gif" => PreviewMode::Gif,
+            "clip" | "webm" | "mp4" => PreviewMode::Clip,
+            "both" | "all" => PreviewMode::Both,
+            _ => return Err(anyhow::anyhow!("未知的预览方式: {}", s)),
+        };
+        Ok(mode)
+    }
+}
+
+/// 缩略图生成状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbnailStatus {
+    // 未生成
+    None,
+    // 已有关键帧, 预览方式需要的其他预览未生成
+    Partial,
+    // 已生成
+    Ready,
+}
+
+/// 缩略图, 通过url获取图片文件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailImage {
+    pub url: String,
+    // base64编码的data uri, 仅在请求base64=true时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    // 关键帧在视频中的时间点(秒), 用于跳转播放
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pts_time: Option<f64>,
+}
+
+/// 搜索结果条目, 附带已入库的文件信息
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchItem {
+    #[serde(flatten)]
+    pub file: SdkFileItem,
+    // 是否已入库
+    pub indexed: bool,
+    pub file_id: Option<u32>,
+    pub hash_key: Option<String>,
+    pub total_frame: Option<u32>,
+    // 视频播放地址, 已入库时返回
+    pub video_url: Option<String>,
+    // WebVTT缩略图轨道地址, 雪碧图已生成时返回
+    pub thumbnail_track_url: Option<String>,
+    // 联系表地址, 已生成时返回
+    pub contact_sheet_url: Option<String>,
+    // 缩略图状态
+    pub thumbnail_status: ThumbnailStatus,
+}
+
+/// 分页结果
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    // 实际查询语句
+    pub query: String,
+    // 总数
+    pub total: u32,
+    pub offset: u32,
+    pub limit: u32,
+    pub items: Vec<T>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -0,0 +1,145 @@
+use crate::model::{MediaInfo, MediaStream};
+use crate::process;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::process::Command;
+use tokio::time::Instant;
+
+/// ffprobe解析结果
+#[derive(Debug, Clone)]
+pub struct ProbeResult {
+    pub info: MediaInfo,
+    pub streams: Vec<MediaStream>,
+}
+
+impl ProbeResult {
+    /// 第一个视频流
+    pub fn video_stream(&self) -> Option<&MediaStream> {
+        self.streams.iter().find(|s| s.codec_type == "video")
+    }
+
+    /// 视频总帧数: 优先使用nb_frames, 缺失时(常见于mkv/rmvb)使用 时长×帧率 估算
+    pub fn total_frames(&self) -> Option<u32> {
+        let video = self.video_stream()?;
+        if let Some(nb_frames) = video.nb_frames.filter(|n| *n > 0) {
+            return Some(nb_frames);
+        }
+        let duration = self.info.duration?;
+        let fps = video.frame_rate?;
+        Some((duration * fps).round() as u32)
+    }
+}
+
+/// 调用ffprobe获取视频的完整信息
+pub async fn probe(file_path: &str) -> Result<ProbeResult> {
+    // ffprobe -v error -show_format -show_streams -of json ${filePath}
+    let mut cmd = Command::new("ffprobe");
+    cmd.arg("-v")
+        .arg("error")
+        .arg("-show_format")
+        .arg("-show_streams")
+        .arg("-of")
+        .arg("json")
+        .arg(file_path);
+    // 执行命令并等待输出
+    let deadline = Instant::now() + process::TIMEOUT.probe;
+    let output = process::output(cmd, deadline, None).await?;
+    // 检查命令是否成功执行
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("ffprobe获取视频信息失败: {}", stderr));
+    }
+    let output = serde_json::from_slice::<FfprobeOutput>(&output.stdout)?;
+    Ok(output.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    index: u32,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    pix_fmt: Option<String>,
+    channels: Option<u32>,
+    nb_frames: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+impl From<FfprobeOutput> for ProbeResult {
+    fn from(output: FfprobeOutput) -> Self {
+        let format = output.format;
+        let info = MediaInfo {
+            file_id: 0,
+            duration: format
+                .as_ref()
+                .and_then(|f| f.duration.as_deref())
+                .and_then(|d| d.parse().ok()),
+            container: format
+                .as_ref()
+                .and_then(|f| f.format_name.clone())
+                .unwrap_or_default(),
+            bit_rate: format
+                .as_ref()
+                .and_then(|f| f.bit_rate.as_deref())
+                .and_then(|b| b.parse().ok()),
+        };
+        let streams = output
+            .streams
+            .into_iter()
+            .map(|s| {
+                // avg_frame_rate为0/0时使用r_frame_rate
+                let frame_rate = s
+                    .avg_frame_rate
+                    .as_deref()
+                    .and_then(parse_rate)
+                    .or_else(|| s.r_frame_rate.as_deref().and_then(parse_rate));
+                MediaStream {
+                    id: 0,
+                    file_id: 0,
+                    stream_index: s.index,
+                    codec_type: s.codec_type.unwrap_or_default(),
+                    codec_name: s.codec_name,
+                    width: s.width,
+                    height: s.height,
+                    frame_rate,
+                    pix_fmt: s.pix_fmt,
+                    channels: s.channels,
+                    nb_frames: s.nb_frames.and_then(|n| n.parse().ok()),
+                    language: s.tags.get("language").cloned(),
+                    title: s.tags.get("title").cloned(),
+                }
+            })
+            .collect();
+        ProbeResult { info, streams }
+    }
+}
+
+// 解析帧率, 如: 30000/1001, 25/1
+fn parse_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let num = num.parse::<f64>().ok()?;
+    let den = den.parse::<f64>().ok()?;
+    if num <= 0.0 || den <= 0.0 {
+        return None;
+    }
+    Some(num / den)
+}
@@ -0,0 +1,227 @@
+use crate::model::ArtifactKind;
+use anyhow::Result;
+use dotenvy::{dotenv, var};
+use std::process::{Output, Stdio};
+use std::sync::LazyLock;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::time::Instant;
+
+/// 超时错误信息中保留的stderr行数
+const STDERR_TAIL_LINES: usize = 20;
+
+/// 外部命令超时配置
+pub static TIMEOUT: LazyLock<TimeoutConfig> = LazyLock::new(|| {
+    dotenv().ok();
+    TimeoutConfig::from_env()
+});
+
+/// 外部命令(ffprobe/ffmpeg)的超时时间, 生成步骤的超时为该步骤内所有命令的总时间
+#[derive(Debug, Clone)]
+pub struct TimeoutConfig {
+    pub probe: Duration,
+    pub keyframes: Duration,
+    pub gif: Duration,
+    pub sprite: Duration,
+    pub contact_sheet: Duration,
+    pub teaser: Duration,
+}
+
+impl TimeoutConfig {
+    /// 从环境变量读取
+    /// `PROBE_TIMEOUT_SECS`: ffprobe超时(秒), 默认60
+    /// `KEYFRAMES_TIMEOUT_SECS`: 生成关键帧超时(秒), 默认1800
+    /// `GIF_TIMEOUT_SECS`: 生成预览动图超时(秒), 默认300
+    /// `SPRITE_TIMEOUT_SECS`: 生成雪碧图超时(秒), 默认300
+    /// `CONTACT_SHEET_TIMEOUT_SECS`: 生成联系表超时(秒), 默认600
+    /// `TEASER_TIMEOUT_SECS`: 生成短视频预览片段超时(秒), 默认600
+    pub fn from_env() -> Self {
+        let secs = |key: &str, default: u64| {
+            Duration::from_secs(
+                var(key)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(default),
+            )
+        };
+        Self {
+            probe: secs("PROBE_TIMEOUT_SECS", 60),
+            keyframes: secs("KEYFRAMES_TIMEOUT_SECS", 1800),
+            gif: secs("GIF_TIMEOUT_SECS", 300),
+            sprite: secs("SPRITE_TIMEOUT_SECS", 300),
+            contact_sheet: secs("CONTACT_SHEET_TIMEOUT_SECS", 600),
+            teaser: secs("TEASER_TIMEOUT_SECS", 600),
+        }
+    }
+
+    /// 生成步骤的超时时间
+    pub fn stage(&self, kind: ArtifactKind) -> Duration {
+        match kind {
+            ArtifactKind::Keyframes => self.keyframes,
+            ArtifactKind::Gif => self.gif,
+            ArtifactKind::Sprite => self.sprite,
+            ArtifactKind::ContactSheet => self.contact_sheet,
+            ArtifactKind::Teaser => self.teaser,
+        }
+    }
+}
+
+/// 外部命令执行超时, stderr为超时前已输出的最后几行
+#[derive(Error, Debug)]
+#[error("{program}执行超时: {stderr}")]
+pub struct TimeoutError {
+    pub program: String,
+    pub stderr: String,
+}
+
+/// 解析ffmpeg `-progress pipe:1`输出的进度(命令需带该参数)
+pub struct Progress<'a> {
+    // 输出的总时长(秒)
+    pub total_secs: f64,
+    // 以0~1的比例回调
+    pub on_progress: &'a (dyn Fn(f64) + Sync),
+}
+
+/// 执行命令并等待输出, 超过deadline时结束进程并返回[TimeoutError];
+/// 返回的future被drop(如任务被取消)时子进程也会被结束
+/// progress不为None时stdout用于解析进度, 返回的Output不包含stdout
+pub async fn output(
+    mut cmd: Command,
+    deadline: Instant,
+    progress: Option<Progress<'_>>,
+) -> Result<Output> {
+    let program = cmd.as_std().get_program().to_string_lossy().to_string();
+    let mut child = cmd
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("stdout未设置为piped");
+    let mut stderr = child.stderr.take().expect("stderr未设置为piped");
+    let mut out_buf = vec![];
+    let mut err_buf = vec![];
+    let result = {
+        let read_stdout = async {
+            match &progress {
+                Some(progress) => read_progress(&mut stdout, progress).await,
+                None => read_all(&mut stdout, &mut out_buf).await,
+            }
+        };
+        let run = async {
+            let (status, (), ()) = tokio::join!(
+                child.wait(),
+                read_stdout,
+                read_all(&mut stderr, &mut err_buf)
+            );
+            status
+        };
+        tokio::time::timeout_at(deadline, run).await
+    };
+    match result {
+        Ok(status) => Ok(Output {
+            status: status?,
+            stdout: out_buf,
+            stderr: err_buf,
+        }),
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(TimeoutError {
+                program,
+                stderr: stderr_tail(&err_buf),
+            }
+            .into())
+        }
+    }
+}
+
+// 读取全部输出, 按块追加以便超时中断时保留已读取的部分
+async fn read_all(reader: &mut (impl AsyncRead + Unpin), buf: &mut Vec<u8>) {
+    let mut chunk = [0u8; 8192];
+    while let Ok(n) = reader.read(&mut chunk).await
+        && n > 0
+    {
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+// 逐行解析进度, 直到输出结束
+async fn read_progress(reader: &mut (impl AsyncRead + Unpin), progress: &Progress<'_>) {
+    let mut reader = BufReader::new(reader);
+    let mut line = vec![];
+    while reader
+        .read_until(b'\n', &mut line)
+        .await
+        .is_ok_and(|n| n > 0)
+    {
+        if let Some(secs) = parse_progress_time(&String::from_utf8_lossy(&line))
+            && progress.total_secs > 0.0
+        {
+            (progress.on_progress)(secs / progress.total_secs);
+        }
+        line.clear();
+    }
+}
+
+// 解析progress输出中的已处理时长(秒), 如: out_time_us=12345678, 未知时为N/A
+fn parse_progress_time(line: &str) -> Option<f64> {
+    line.strip_prefix("out_time_us=")?
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|us| *us >= 0)
+        .map(|us| us as f64 / 1_000_000.0)
+}
+
+// stderr的最后几行
+fn stderr_tail(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines = stderr.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_progress_time() {
+        let cases = [
+            ("out_time_us=12345678", Some(12.345678)),
+            ("out_time_us=12345678\n", Some(12.345678)),
+            ("out_time_us=0", Some(0.0)),
+            ("out_time_us=3600000000\r\n", Some(3600.0)),
+            // 开始阶段或没有视频流时为N/A
+            ("out_time_us=N/A", None),
+            ("out_time_us=", None),
+            // 开头的帧可能是负数
+            ("out_time_us=-23220", None),
+            ("out_time_us=1.5", None),
+            ("out_time=00:00:12.345678", None),
+            ("out_time_ms=12345678", None),
+            ("frame=120", None),
+            ("progress=end", None),
+            ("", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(parse_progress_time(line), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_stderr_tail() {
+        let lines = (1..=STDERR_TAIL_LINES + 5)
+            .map(|i| format!("line{}", i))
+            .collect::<Vec<_>>();
+        let cases = [
+            (String::new(), String::new()),
+            ("error\n".to_string(), "error".to_string()),
+            (lines.join("\n"), lines[5..].join("\n")),
+        ];
+        for (stderr, expected) in cases {
+            assert_eq!(stderr_tail(stderr.as_bytes()), expected, "{:?}", stderr);
+        }
+    }
+}
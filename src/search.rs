@@ -0,0 +1,450 @@
+use crate::errors::IError;
+use crate::local_index::LocalIndexSearcher;
+use crate::model::CodeRequest;
+use dotenvy::{dotenv, var};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use tracing::info;
+
+/// 默认搜索的视频扩展名
+pub const VIDEO_EXTENSIONS: &[&str] =
+    &["mp4", "avi", "wmv", "mkv", "mpg", "rmvb", "iso", "bt.xltd"];
+
+/// 默认最小文件大小(128MB)
+pub const MIN_FILE_SIZE: u64 = 128 * 1024 * 1024;
+
+/// 默认最大结果数
+pub const MAX_RESULTS: u32 = 20;
+
+/// 默认搜索条件(从环境变量读取)
+pub static SEARCH_DEFAULTS: LazyLock<SearchRequest> = LazyLock::new(|| {
+    dotenv().ok();
+    SearchRequest::from_env()
+});
+
+/// 单次请求最大结果数的上限, 避免一次请求处理大量文件(如/thumbnails为每个文件计算hash并加入任务)
+/// `SEARCH_MAX_RESULTS_LIMIT`: 上限, 默认200
+pub static MAX_RESULTS_LIMIT: LazyLock<u32> = LazyLock::new(|| {
+    dotenv().ok();
+    var("SEARCH_MAX_RESULTS_LIMIT")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .unwrap_or(200)
+        .max(1)
+});
+
+/// 文件搜索后端
+pub trait FileSearcher: Send + Sync {
+    /// 后端名称
+    fn name(&self) -> &'static str;
+
+    /// 根据搜索条件搜索视频文件
+    fn search<'a>(&'a self, req: &'a SearchRequest) -> BoxFuture<'a, Result<SearchResult, IError>>;
+}
+
+/// 搜索结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    // 实际查询语句
+    pub query: String,
+    // 匹配的总数(不受分页影响)
+    pub total: u32,
+    // 当前页的文件列表
+    pub items: Vec<SdkFileItem>,
+}
+
+/// 排序方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    #[serde(alias = "name")]
+    NameAsc,
+    NameDesc,
+    #[serde(alias = "path")]
+    PathAsc,
+    PathDesc,
+    #[serde(alias = "size")]
+    SizeAsc,
+    SizeDesc,
+    #[serde(alias = "date_created")]
+    DateCreatedAsc,
+    DateCreatedDesc,
+}
+
+impl FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sort = match s.to_lowercase().as_str() {
+            "name" | "name_asc" => SortOrder::NameAsc,
+            "name_desc" => SortOrder::NameDesc,
+            "path" | "path_asc" => SortOrder::PathAsc,
+            "path_desc" => SortOrder::PathDesc,
+            "size" | "size_asc" => SortOrder::SizeAsc,
+            "size_desc" => SortOrder::SizeDesc,
+            "date_created" | "date_created_asc" => SortOrder::DateCreatedAsc,
+            "date_created_desc" => SortOrder::DateCreatedDesc,
+            _ => return Err(anyhow::anyhow!("未知的排序方式: {}", s)),
+        };
+        Ok(sort)
+    }
+}
+
+/// 结构化的搜索条件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchRequest {
+    // 搜索关键字, 空格分隔的多个关键字需同时匹配
+    pub keyword: String,
+    // 最小文件大小(字节)
+    pub min_size: Option<u64>,
+    // 最大文件大小(字节)
+    pub max_size: Option<u64>,
+    // 扩展名白名单(不带`.`), 为空时不限制
+    pub extensions: Vec<String>,
+    // 最大结果数(每页数量)
+    pub max_results: u32,
+    // 跳过的结果数(分页偏移)
+    pub offset: u32,
+    // 排序方式
+    pub sort: SortOrder,
+    // 是否区分大小写
+    pub match_case: bool,
+    // 只搜索这些目录下的文件
+    pub include_paths: Vec<String>,
+    // 排除这些目录下的文件
+    pub exclude_paths: Vec<String>,
+}
+
+impl Default for SearchRequest {
+    fn default() -> Self {
+        Self {
+            keyword: String::new(),
+            min_size: Some(MIN_FILE_SIZE),
+            max_size: None,
+            extensions: VIDEO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            max_results: MAX_RESULTS,
+            offset: 0,
+            sort: SortOrder::default(),
+            match_case: false,
+            include_paths: vec![],
+            exclude_paths: vec![],
+        }
+    }
+}
+
+impl SearchRequest {
+    /// 从环境变量读取默认搜索条件, 未配置的项使用内置默认值
+    /// `SEARCH_MIN_SIZE`/`SEARCH_MAX_SIZE`: 文件大小, 支持KB/MB/GB后缀, 0表示不限制
+    /// `SEARCH_EXTENSIONS`: 扩展名, 逗号分隔
+    /// `SEARCH_MAX_RESULTS`: 最大结果数, 不超过`SEARCH_MAX_RESULTS_LIMIT`
+    /// `SEARCH_SORT`: 排序方式(name|name_desc|path|path_desc|size|size_desc|date_created|date_created_desc)
+    /// `SEARCH_MATCH_CASE`: 是否区分大小写
+    /// `SEARCH_INCLUDE_PATHS`/`SEARCH_EXCLUDE_PATHS`: 目录, 分号分隔
+    pub fn from_env() -> Self {
+        let mut req = Self::default();
+        if let Ok(size) = var("SEARCH_MIN_SIZE") {
+            req.min_size = parse_size(&size).filter(|s| *s > 0);
+        }
+        if let Ok(size) = var("SEARCH_MAX_SIZE") {
+            req.max_size = parse_size(&size).filter(|s| *s > 0);
+        }
+        if let Ok(exts) = var("SEARCH_EXTENSIONS") {
+            req.extensions = split_extensions(&exts);
+        }
+        if let Some(max) = var("SEARCH_MAX_RESULTS").ok().and_then(|s| s.parse().ok()) {
+            req.max_results = max;
+        }
+        if let Some(sort) = var("SEARCH_SORT").ok().and_then(|s| s.parse().ok()) {
+            req.sort = sort;
+        }
+        if let Some(match_case) = var("SEARCH_MATCH_CASE").ok().and_then(|s| s.parse().ok()) {
+            req.match_case = match_case;
+        }
+        if let Ok(paths) = var("SEARCH_INCLUDE_PATHS") {
+            req.include_paths = split_paths(&paths);
+        }
+        if let Ok(paths) = var("SEARCH_EXCLUDE_PATHS") {
+            req.exclude_paths = split_paths(&paths);
+        }
+        req
+    }
+
+    /// 关键字拆分后的列表
+    pub fn terms(&self) -> Vec<&str> {
+        self.keyword.split_whitespace().collect()
+    }
+
+    /// 转换为everything搜索语法
+    pub fn query_string(&self) -> String {
+        let mut parts = vec![];
+        if let Some(min) = self.min_size {
+            parts.push(format!("size:>{}", min));
+        }
+        if let Some(max) = self.max_size {
+            parts.push(format!("size:<{}", max));
+        }
+        if !self.extensions.is_empty() {
+            let exts = self
+                .extensions
+                .iter()
+                .map(|ext| format!(".{}", ext))
+                .collect::<Vec<_>>()
+                .join("|");
+            parts.push(exts);
+        }
+        if !self.include_paths.is_empty() {
+            let paths = self
+                .include_paths
+                .iter()
+                .map(|p| format!("path:\"{}\"", p))
+                .collect::<Vec<_>>()
+                .join("|");
+            parts.push(format!("<{}>", paths));
+        }
+        for path in &self.exclude_paths {
+            parts.push(format!("!path:\"{}\"", path));
+        }
+        if !self.keyword.is_empty() {
+            parts.push(self.keyword.clone());
+        }
+        parts.join(" ")
+    }
+}
+
+impl From<&CodeRequest> for SearchRequest {
+    /// 以默认搜索条件为基础, 使用请求参数覆盖, 最大结果数不超过配置的上限
+    fn from(code_req: &CodeRequest) -> Self {
+        let mut req = SEARCH_DEFAULTS.clone();
+        req.keyword = code_req.code.clone();
+        if let Some(size) = code_req.min_size {
+            req.min_size = Some(size).filter(|s| *s > 0);
+        }
+        if let Some(size) = code_req.max_size {
+            req.max_size = Some(size).filter(|s| *s > 0);
+        }
+        if let Some(exts) = &code_req.exts {
+            req.extensions = split_extensions(exts);
+        }
+        if let Some(max) = code_req.max_results {
+            req.max_results = max;
+        }
+        if let Some(offset) = code_req.offset {
+            req.offset = offset;
+        }
+        if let Some(sort) = code_req.sort {
+            req.sort = sort;
+        }
+        if let Some(match_case) = code_req.match_case {
+            req.match_case = match_case;
+        }
+        if let Some(paths) = &code_req.include_paths {
+            req.include_paths = split_paths(paths);
+        }
+        if let Some(paths) = &code_req.exclude_paths {
+            req.exclude_paths = split_paths(paths);
+        }
+        req.max_results = req.max_results.min(*MAX_RESULTS_LIMIT);
+        req
+    }
+}
+
+// 解析文件大小, 如: 1024, 128MB, 1.5GB; 负数或超出u64范围时返回None
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim().to_uppercase();
+    let (num, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s.as_str(), ""),
+    };
+    let unit = match unit.trim() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let size = num.trim().parse::<f64>().ok()? * unit as f64;
+    // f64转u64会饱和截断, 需先检查范围
+    (size.is_finite() && size >= 0.0 && size < u64::MAX as f64).then_some(size as u64)
+}
+
+// 扩展名列表, 逗号分隔, 去掉开头的`.`
+fn split_extensions(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect()
+}
+
+// 目录列表, 分号分隔
+fn split_paths(s: &str) -> Vec<String> {
+    s.split(';')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// 根据环境变量`SEARCH_BACKEND`创建搜索后端(everything|local)
+/// 未配置时: windows默认everything, 其他平台默认local; 非windows平台配置everything时使用local
+pub fn from_env(pool: SqlitePool) -> anyhow::Result<Arc<dyn FileSearcher>> {
+    let default_backend = if cfg!(windows) { "everything" } else { "local" };
+    let backend = var("SEARCH_BACKEND").unwrap_or(default_backend.to_string());
+    let searcher: Arc<dyn FileSearcher> = match backend.trim().to_lowercase().as_str() {
+        #[cfg(windows)]
+        "everything" => Arc::new(crate::es::EverythingSearcher),
+        #[cfg(not(windows))]
+        "everything" => {
+            tracing::warn!("everything搜索后端只支持windows, 使用local");
+            Arc::new(LocalIndexSearcher::from_env(pool))
+        }
+        "local" => Arc::new(LocalIndexSearcher::from_env(pool)),
+        _ => return Err(anyhow::anyhow!("未知的搜索后端: {}", backend)),
+    };
+    info!("搜索后端: {}", searcher.name());
+    Ok(searcher)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SdkFileItem {
+    pub index: u32,
+    // 完全路径
+    pub filepath: String,
+    // 文件名
+    pub filename: String,
+    // 扩展名
+    pub ext: String,
+    // 文件路径
+    pub path: String,
+    // 文件大小
+    pub size: u64,
+    // 创建时间
+    pub date_created: String,
+    // 是否是目录
+    pub is_dir: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        let cases = [
+            ("1024", Some(1024)),
+            (" 512 ", Some(512)),
+            ("10B", Some(10)),
+            ("2k", Some(2 * 1024)),
+            ("2KB", Some(2 * 1024)),
+            ("128MB", Some(128 * 1024 * 1024)),
+            ("128 mb", Some(128 * 1024 * 1024)),
+            ("1.5GB", Some(1536 * 1024 * 1024)),
+            ("4G", Some(4 * 1024 * 1024 * 1024)),
+            ("0", Some(0)),
+            ("", None),
+            ("MB", None),
+            ("12TB", None),
+            ("12X", None),
+            ("abc", None),
+            ("-1", None),
+            ("-1MB", None),
+            ("NaN", None),
+            ("inf", None),
+            ("18446744073709551616", None),
+            ("99999999999G", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_size(input), expected, "parse_size({:?})", input);
+        }
+    }
+
+    #[test]
+    fn test_split_extensions() {
+        let cases = [
+            ("mp4,.MKV, avi", vec!["mp4", "mkv", "avi"]),
+            (",,.ts,", vec!["ts"]),
+            ("", vec![]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                split_extensions(input),
+                expected,
+                "split_extensions({:?})",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_string() {
+        let base = SearchRequest {
+            keyword: String::new(),
+            min_size: None,
+            max_size: None,
+            extensions: vec![],
+            include_paths: vec![],
+            exclude_paths: vec![],
+            ..SearchRequest::default()
+        };
+        let cases = [
+            (base.clone(), ""),
+            (
+                SearchRequest {
+                    keyword: "abc-123".to_string(),
+                    ..base.clone()
+                },
+                "abc-123",
+            ),
+            (
+                SearchRequest {
+                    keyword: "abc".to_string(),
+                    min_size: Some(1024),
+                    max_size: Some(2048),
+                    ..base.clone()
+                },
+                "size:>1024 size:<2048 abc",
+            ),
+            (
+                SearchRequest {
+                    keyword: "abc".to_string(),
+                    extensions: vec!["mp4".to_string(), "mkv".to_string()],
+                    ..base.clone()
+                },
+                ".mp4|.mkv abc",
+            ),
+            (
+                SearchRequest {
+                    keyword: "abc".to_string(),
+                    include_paths: vec!["D:\\video".to_string(), "E:\\movie".to_string()],
+                    exclude_paths: vec!["D:\\video\\tmp".to_string()],
+                    ..base.clone()
+                },
+                "<path:\"D:\\video\"|path:\"E:\\movie\"> !path:\"D:\\video\\tmp\" abc",
+            ),
+        ];
+        for (req, expected) in cases {
+            assert_eq!(req.query_string(), expected, "{:?}", req);
+        }
+    }
+
+    #[test]
+    fn test_max_results_limit() {
+        let limit = *MAX_RESULTS_LIMIT;
+        let cases = [
+            (r#"{"code":"abc"}"#, SEARCH_DEFAULTS.max_results.min(limit)),
+            (r#"{"code":"abc","limit":5}"#, 5.min(limit)),
+            (r#"{"code":"abc","maxResults":100000}"#, limit),
+            (r#"{"code":"abc","limit":4294967295}"#, limit),
+        ];
+        for (json, expected) in cases {
+            let code_req = serde_json::from_str::<CodeRequest>(json).unwrap();
+            let req = SearchRequest::from(&code_req);
+            assert_eq!(req.max_results, expected, "{}", json);
+        }
+    }
+}
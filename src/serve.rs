@@ -0,0 +1,167 @@
+use crate::errors::IError;
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use headers::{ETag, HeaderMapExt, IfNoneMatch, LastModified};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tower_http::services::ServeFile;
+
+/// 缩略图的缓存策略: 内容按hash_key存储, 很少变化, 过期后通过ETag协商
+pub const THUMBNAIL_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// 视频的缓存策略: 原文件可能被替换, 每次通过ETag协商
+pub const VIDEO_CACHE_CONTROL: &str = "no-cache";
+
+/// 以流的方式返回文件, 带Content-Type/ETag/Last-Modified/Cache-Control,
+/// 支持If-None-Match/If-Modified-Since协商缓存(304)和Range请求
+pub async fn serve_file(
+    path: &Path,
+    req_headers: &HeaderMap,
+    cache_control: &'static str,
+) -> Result<Response, IError> {
+    serve_file_with_mime(path, req_headers, cache_control, None).await
+}
+
+/// 同[serve_file], mime不为None时覆盖按扩展名推断的Content-Type
+pub async fn serve_file_with_mime(
+    path: &Path,
+    req_headers: &HeaderMap,
+    cache_control: &'static str,
+    mime: Option<&'static str>,
+) -> Result<Response, IError> {
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Err(IError::NotFound(path.display().to_string())),
+    };
+    let etag = gen_etag(&metadata);
+    let cache_control = HeaderValue::from_static(cache_control);
+    // If-None-Match优先于If-Modified-Since, 后者由ServeFile处理
+    if let Some(if_none_match) = req_headers.typed_get::<IfNoneMatch>()
+        && !if_none_match.precondition_passes(&etag)
+    {
+        let mut res = StatusCode::NOT_MODIFIED.into_response();
+        res.headers_mut().typed_insert(etag);
+        if let Ok(modified) = metadata.modified() {
+            res.headers_mut().typed_insert(LastModified::from(modified));
+        }
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, cache_control);
+        return Ok(res);
+    }
+    let mut req = Request::new(Body::empty());
+    *req.headers_mut() = req_headers.clone();
+    let res = ServeFile::new(path)
+        .try_call(req)
+        .await
+        .map_err(|e| IError::Internal(e.into()))?;
+    let mut res = res.map(Body::new);
+    if res.status().is_success() || res.status() == StatusCode::NOT_MODIFIED {
+        res.headers_mut().typed_insert(etag);
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, cache_control);
+        if let Some(mime) = mime.filter(|_| res.status().is_success()) {
+            res.headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(mime));
+        }
+    }
+    Ok(res)
+}
+
+/// 视频文件的访问url
+pub fn video_url(hash_key: &str) -> String {
+    format!("/files/{}/video", hash_key)
+}
+
+/// 视频的mime类型: 优先根据ffprobe识别的容器格式, 未知时根据扩展名
+pub fn video_mime(container: Option<&str>, file_path: &str) -> &'static str {
+    let ext = Path::new(file_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    // format_name可能包含多个名称, 如: mov,mp4,m4a,3gp,3g2,mj2
+    let formats = container
+        .map(|c| c.split(',').collect::<Vec<_>>())
+        .unwrap_or_default();
+    let has = |name: &str| formats.contains(&name);
+    match ext.as_str() {
+        "webm" if has("matroska") => "video/webm",
+        _ if has("matroska") => "video/x-matroska",
+        "mov" if has("mov") => "video/quicktime",
+        _ if has("mp4") => "video/mp4",
+        _ if has("avi") => "video/x-msvideo",
+        _ if has("asf") => "video/x-ms-asf",
+        _ if has("flv") => "video/x-flv",
+        _ if has("mpegts") => "video/mp2t",
+        _ if has("mpeg") => "video/mpeg",
+        _ if has("rm") => "application/vnd.rn-realmedia-vbr",
+        "mp4" | "m4v" => "video/mp4",
+        "mkv" => "video/x-matroska",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "avi" => "video/x-msvideo",
+        "wmv" => "video/x-ms-wmv",
+        "flv" => "video/x-flv",
+        "ts" | "m2ts" => "video/mp2t",
+        "mpg" | "mpeg" => "video/mpeg",
+        "rmvb" | "rm" => "application/vnd.rn-realmedia-vbr",
+        "3gp" => "video/3gpp",
+        _ => "application/octet-stream",
+    }
+}
+
+// 根据文件大小和修改时间生成ETag
+fn gen_etag(metadata: &std::fs::Metadata) -> ETag {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+        .parse()
+        .expect("ETag格式错误")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_video_mime() {
+        let cases = [
+            // 容器格式优先
+            (Some("matroska,webm"), "a.mkv", "video/x-matroska"),
+            (Some("matroska,webm"), "a.WEBM", "video/webm"),
+            (Some("matroska,webm"), "a.mp4", "video/x-matroska"),
+            (Some("mov,mp4,m4a,3gp,3g2,mj2"), "a.mov", "video/quicktime"),
+            (Some("mov,mp4,m4a,3gp,3g2,mj2"), "a.mp4", "video/mp4"),
+            (Some("mov,mp4,m4a,3gp,3g2,mj2"), "a.3gp", "video/mp4"),
+            (Some("avi"), "a.avi", "video/x-msvideo"),
+            (Some("asf"), "a.wmv", "video/x-ms-asf"),
+            (Some("flv"), "a.flv", "video/x-flv"),
+            (Some("mpegts"), "a.m2ts", "video/mp2t"),
+            (Some("mpeg"), "a.mpg", "video/mpeg"),
+            (Some("rm"), "a.rmvb", "application/vnd.rn-realmedia-vbr"),
+            // 未知容器格式时根据扩展名
+            (Some("unknown"), "a.mkv", "video/x-matroska"),
+            (None, "a.M4V", "video/mp4"),
+            (None, "D:\\video\\a.wmv", "video/x-ms-wmv"),
+            (None, "/video/a.ts", "video/mp2t"),
+            (None, "a.rm", "application/vnd.rn-realmedia-vbr"),
+            (None, "a.3gp", "video/3gpp"),
+            (None, "a.iso", "application/octet-stream"),
+            (None, "a", "application/octet-stream"),
+            (Some(""), "", "application/octet-stream"),
+        ];
+        for (container, file_path, expected) in cases {
+            assert_eq!(
+                video_mime(container, file_path),
+                expected,
+                "video_mime({:?}, {:?})",
+                container,
+                file_path
+            );
+        }
+    }
+}
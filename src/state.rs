@@ -0,0 +1,34 @@
+use crate::job::JobQueue;
+use crate::search::FileSearcher;
+use axum::extract::FromRef;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+/// 应用共享状态
+#[derive(Clone)]
+pub struct AppState {
+    // 数据库连接池
+    pub pool: SqlitePool,
+    // 文件搜索后端
+    pub searcher: Arc<dyn FileSearcher>,
+    // 缩略图任务队列
+    pub jobs: JobQueue,
+}
+
+impl FromRef<AppState> for SqlitePool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn FileSearcher> {
+    fn from_ref(state: &AppState) -> Self {
+        state.searcher.clone()
+    }
+}
+
+impl FromRef<AppState> for JobQueue {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
@@ -1,92 +1,1470 @@
-use crate::model::FileInfo;
+use crate::dao;
+use crate::hwaccel::{self, HwAccel};
+use crate::model::{
+    Artifact, ArtifactKind, ArtifactState, FileInfo, JobProgress, Keyframe, MediaInfo, MediaStream,
+    PreviewMode, ThumbnailEvent, ThumbnailStatus,
+};
+use crate::process::{self, Progress};
 use anyhow::Result;
 use dotenvy::{dotenv, var};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::LazyLock;
+use thiserror::Error;
 use tokio::process::Command;
-use tracing::info;
+use tokio::time::Instant;
+use tracing::{info, warn};
 
 pub static OUTPUT_DIR: LazyLock<String> = LazyLock::new(|| {
     dotenv().ok();
     var("OUTPUT_DIR").unwrap_or_else(|_| "D:/video-data".to_string())
 });
 
-/// 获取视频总帧数
-pub async fn obtain_total_frame_count(file_path: &str) -> Result<u32> {
-    // 获取输出目录
-    let output_path = OUTPUT_DIR.as_str();
-    let filename = &FileInfo::obtain_filename(&file_path);
-    // ffprobe -v error -select_streams v:0 -show_entries stream=nb_frames -of default=noprint_wrappers=1:nokey=1 ${filePath}
-    let mut cmd = Command::new("ffprobe");
-    cmd.arg("-v")
-        .arg("error")
-        .arg("-select_streams")
-        .arg("v:0")
-        .arg("-show_entries")
-        .arg("stream=nb_frames")
-        .arg("-of")
-        .arg("default=noprint_wrappers=1:nokey=1")
-        .arg(file_path)
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped());
-    // 执行命令并等待输出
-    let output = cmd.output().await?;
-    // 检查命令是否成功执行
-    if !output.status.success() {
-        let stderr = String::from_utf8(output.stderr)?;
-        return Err(anyhow::anyhow!("ffprobe获取视频总帧失败: {}", stderr));
+/// 没有时长信息时按该时长(秒)估算生成进度
+const DEFAULT_DURATION_SECS: f64 = 600.0;
+
+/// 临时目录名前缀, 生成过程中写入输出目录下的临时目录, 成功后再移动到正式位置
+const TEMP_DIR_PREFIX: &str = ".tmp-";
+
+/// 关键帧图片配置
+pub static KEYFRAME: LazyLock<KeyframeConfig> = LazyLock::new(|| {
+    dotenv().ok();
+    KeyframeConfig::from_env()
+});
+
+/// 预览动图格式(环境变量`ANIMATION_FORMAT`: gif|webp), 默认gif
+pub static ANIMATION: LazyLock<AnimationFormat> = LazyLock::new(|| {
+    dotenv().ok();
+    var("ANIMATION_FORMAT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default()
+});
+
+/// 关键帧图片格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    // 无损, 忽略质量设置
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl ImageFormat {
+    /// 文件扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+        }
+    }
+
+    /// ffmpeg编码参数, quality范围0-100, 越大质量越好
+    pub fn encoder_args(&self, quality: u32) -> Vec<String> {
+        let quality = quality.min(100);
+        match self {
+            ImageFormat::Png => vec![],
+            // mjpeg的q:v范围2-31, 越小质量越好
+            ImageFormat::Jpeg => vec!["-q:v".into(), (2 + (100 - quality) * 29 / 100).to_string()],
+            ImageFormat::Webp => vec![
+                "-c:v".into(),
+                "libwebp".into(),
+                "-quality".into(),
+                quality.to_string(),
+            ],
+            // libaom的crf范围0-63, 越小质量越好
+            ImageFormat::Avif => vec![
+                "-c:v".into(),
+                "libaom-av1".into(),
+                "-still-picture".into(),
+                "1".into(),
+                "-crf".into(),
+                ((100 - quality) * 63 / 100).to_string(),
+                "-cpu-used".into(),
+                "6".into(),
+            ],
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = match s.to_lowercase().as_str() {
+            "png" => ImageFormat::Png,
+            "jpg" | "jpeg" => ImageFormat::Jpeg,
+            "webp" => ImageFormat::Webp,
+            "avif" => ImageFormat::Avif,
+            _ => return Err(anyhow::anyhow!("未知的图片格式: {}", s)),
+        };
+        Ok(format)
+    }
+}
+
+/// 关键帧选取方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyframeStrategy {
+    // 只解码关键帧, 每隔固定数量取一帧, 速度最快
+    #[default]
+    Interval,
+    // 解码所有帧, 按场景变化分数选取, 最有代表性但速度最慢
+    Scene,
+    // 按视频时长均匀选取固定数量的时间点
+    Even,
+}
+
+impl FromStr for KeyframeStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let strategy = match s.to_lowercase().as_str() {
+            "interval" => KeyframeStrategy::Interval,
+            "scene" => KeyframeStrategy::Scene,
+            "even" => KeyframeStrategy::Even,
+            _ => return Err(anyhow::anyhow!("未知的关键帧选取方式: {}", s)),
+        };
+        Ok(strategy)
+    }
+}
+
+// 解析`目录=方式`列表, 忽略无效项
+fn parse_library_strategies(s: &str) -> Vec<(PathBuf, KeyframeStrategy)> {
+    std::env::split_paths(s)
+        .filter_map(|entry| {
+            let entry = entry.to_string_lossy().into_owned();
+            let parsed = entry
+                .rsplit_once('=')
+                .and_then(|(root, strategy)| Some((root.trim(), strategy.trim().parse().ok()?)))
+                .filter(|(root, _)| !root.is_empty());
+            if parsed.is_none() && !entry.trim().is_empty() {
+                warn!("忽略无效的媒体库关键帧选取方式: {}", entry);
+            }
+            parsed.map(|(root, strategy)| (PathBuf::from(root), strategy))
+        })
+        .collect()
+}
+
+/// 关键帧的选取方式、图片格式、质量和尺寸
+#[derive(Debug, Clone)]
+pub struct KeyframeConfig {
+    pub strategy: KeyframeStrategy,
+    // interval: 每隔多少个关键帧取一帧
+    pub interval: u32,
+    // scene: 场景变化阈值(0-1), 越小选取的帧越多
+    pub scene_threshold: f64,
+    // even: 选取的帧数
+    pub count: u32,
+    pub format: ImageFormat,
+    // 质量0-100, png忽略
+    pub quality: u32,
+    // 最大宽度, 0表示不限制
+    pub max_width: u32,
+    // 最大高度, 0表示不限制
+    pub max_height: u32,
+    // 按媒体库目录指定的选取方式, 覆盖strategy
+    pub library_strategies: Vec<(PathBuf, KeyframeStrategy)>,
+}
+
+impl KeyframeConfig {
+    /// 从环境变量创建
+    /// `KEYFRAME_STRATEGY`: 选取方式interval|scene|even, 默认interval
+    /// `KEYFRAME_INTERVAL`: interval方式每隔多少个关键帧取一帧, 默认10
+    /// `KEYFRAME_SCENE_THRESHOLD`: scene方式的场景变化阈值, 默认0.3
+    /// `KEYFRAME_COUNT`: even方式选取的帧数, 默认20
+    /// `KEYFRAME_FORMAT`: 图片格式png|jpeg|webp|avif, 默认png
+    /// `KEYFRAME_QUALITY`: 质量0-100, 默认80
+    /// `KEYFRAME_MAX_WIDTH`: 最大宽度, 默认320
+    /// `KEYFRAME_MAX_HEIGHT`: 最大高度, 默认0(不限制)
+    /// `KEYFRAME_LIBRARY_STRATEGY`: 按媒体库目录指定选取方式, 格式为`目录=方式`, 多项按系统PATH分隔符分隔,
+    /// 如`D:\anime=scene;D:\movie=even`, 不在这些目录下的文件使用`KEYFRAME_STRATEGY`
+    pub fn from_env() -> Self {
+        let strategy = var("KEYFRAME_STRATEGY")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+        let interval = var("KEYFRAME_INTERVAL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(10);
+        let scene_threshold = var("KEYFRAME_SCENE_THRESHOLD")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|t| (0.0..=1.0).contains(t))
+            .unwrap_or(0.3);
+        let count = var("KEYFRAME_COUNT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(20);
+        let format = var("KEYFRAME_FORMAT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+        let quality = var("KEYFRAME_QUALITY")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(80);
+        let max_width = var("KEYFRAME_MAX_WIDTH")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(320);
+        let max_height = var("KEYFRAME_MAX_HEIGHT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        let library_strategies = var("KEYFRAME_LIBRARY_STRATEGY")
+            .map(|s| parse_library_strategies(&s))
+            .unwrap_or_default();
+        Self {
+            strategy,
+            interval,
+            scene_threshold,
+            count,
+            format,
+            quality,
+            max_width,
+            max_height,
+            library_strategies,
+        }
+    }
+
+    /// 文件所在媒体库使用的配置, 多个目录匹配时取最深的目录
+    pub fn for_file(&self, file_path: &str) -> KeyframeConfig {
+        let mut config = self.clone();
+        if let Some((_, strategy)) = self
+            .library_strategies
+            .iter()
+            .filter(|(root, _)| Path::new(file_path).starts_with(root))
+            .max_by_key(|(root, _)| root.components().count())
+        {
+            config.strategy = *strategy;
+        }
+        config
+    }
+
+    /// 生成参数, 用于判断是否需要重新生成
+    pub fn params(&self) -> String {
+        let strategy = match self.strategy {
+            KeyframeStrategy::Interval => format!("interval={}", self.interval),
+            KeyframeStrategy::Scene => format!("scene={}", self.scene_threshold),
+            KeyframeStrategy::Even => format!("even={}", self.count),
+        };
+        format!(
+            "{};format={};quality={};max={}x{}",
+            strategy,
+            self.format.extension(),
+            self.quality,
+            self.max_width,
+            self.max_height
+        )
+    }
+
+    /// interval/scene方式的选帧滤镜
+    pub fn select_filter(&self) -> String {
+        match self.strategy {
+            KeyframeStrategy::Scene => format!("select='gt(scene\\,{})'", self.scene_threshold),
+            _ => format!("select='not(mod(n\\,{}))'", self.interval),
+        }
+    }
+
+    /// 缩放滤镜, 按比例缩小到最大宽高以内(不放大), 都不限制时保持原尺寸
+    pub fn scale_filter(&self) -> Option<String> {
+        match (self.max_width, self.max_height) {
+            (0, 0) => None,
+            (w, 0) => Some(format!("scale='min({},iw)':-1", w)),
+            (0, h) => Some(format!("scale=-1:'min({},ih)'", h)),
+            (w, h) => Some(format!(
+                "scale='min({},iw)':'min({},ih)':force_original_aspect_ratio=decrease",
+                w, h
+            )),
+        }
+    }
+}
+
+/// 预览动图格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    // 动态webp, 支持真彩色, 体积更小
+    Webp,
+}
+
+impl AnimationFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Webp => "webp",
+        }
+    }
+}
+
+impl FromStr for AnimationFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = match s.to_lowercase().as_str() {
+            "gif" => AnimationFormat::Gif,
+            "webp" => AnimationFormat::Webp,
+            _ => return Err(anyhow::anyhow!("未知的动图格式: {}", s)),
+        };
+        Ok(format)
+    }
+}
+
+/// 雪碧图中每张缩略图的宽高
+const SPRITE_TILE_WIDTH: u32 = 160;
+const SPRITE_TILE_HEIGHT: u32 = 90;
+/// 每张雪碧图的列数和行数
+const SPRITE_COLUMNS: u32 = 10;
+const SPRITE_ROWS: u32 = 10;
+/// WebVTT缩略图轨道文件名
+pub const SPRITE_VTT_NAME: &str = "thumbnails.vtt";
+
+/// 联系表配置
+pub static CONTACT_SHEET: LazyLock<ContactSheetConfig> = LazyLock::new(|| {
+    dotenv().ok();
+    ContactSheetConfig::from_env()
+});
+
+/// 联系表(多帧缩略图拼接的总览图)配置
+#[derive(Debug, Clone)]
+pub struct ContactSheetConfig {
+    // 列数
+    pub columns: u32,
+    // 行数
+    pub rows: u32,
+    // 每张缩略图的宽度
+    pub tile_width: u32,
+    // 输出格式: jpg|png|webp
+    pub format: String,
+    // 字体文件, 文件名包含中文时需要指定支持中文的字体
+    pub font_file: Option<String>,
+}
+
+impl ContactSheetConfig {
+    /// 从环境变量创建
+    /// `CONTACT_SHEET_GRID`: 列数x行数, 默认4x4
+    /// `CONTACT_SHEET_TILE_WIDTH`: 每张缩略图的宽度, 默认320
+    /// `CONTACT_SHEET_FORMAT`: 输出格式jpg|png|webp, 默认jpg
+    /// `CONTACT_SHEET_FONT`: 字体文件路径, 默认使用ffmpeg的默认字体
+    pub fn from_env() -> Self {
+        let (columns, rows) = var("CONTACT_SHEET_GRID")
+            .ok()
+            .and_then(|grid| {
+                let (columns, rows) = grid
+                    .to_lowercase()
+                    .split_once('x')
+                    .map(|(c, r)| (c.trim().parse::<u32>().ok(), r.trim().parse::<u32>().ok()))?;
+                Some((columns?, rows?))
+            })
+            .filter(|(columns, rows)| *columns > 0 && *rows > 0)
+            .unwrap_or((4, 4));
+        let tile_width = var("CONTACT_SHEET_TILE_WIDTH")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|w| *w > 0)
+            .unwrap_or(320);
+        let format = var("CONTACT_SHEET_FORMAT")
+            .ok()
+            .map(|s| s.to_lowercase())
+            .filter(|s| matches!(s.as_str(), "jpg" | "png" | "webp"))
+            .unwrap_or_else(|| "jpg".to_string());
+        let font_file = var("CONTACT_SHEET_FONT").ok().filter(|s| !s.is_empty());
+        Self {
+            columns,
+            rows,
+            tile_width,
+            format,
+            font_file,
+        }
+    }
+
+    /// 生成参数, 用于判断是否需要重新生成
+    pub fn params(&self) -> String {
+        format!(
+            "grid={}x{};tile_width={};format={};font={}",
+            self.columns,
+            self.rows,
+            self.tile_width,
+            self.format,
+            self.font_file.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// 预览方式配置(环境变量`PREVIEW`: gif|clip|both), 默认gif, 可在请求中指定
+pub static PREVIEW: LazyLock<PreviewMode> = LazyLock::new(|| {
+    dotenv().ok();
+    var("PREVIEW")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default()
+});
+
+/// 短视频预览片段配置
+pub static TEASER: LazyLock<TeaserConfig> = LazyLock::new(|| {
+    dotenv().ok();
+    TeaserConfig::from_env()
+});
+
+/// 短视频预览片段: 从视频中均匀截取多段拼接的无声短视频
+#[derive(Debug, Clone)]
+pub struct TeaserConfig {
+    // 输出格式: webm|mp4
+    pub format: String,
+    // 片段数
+    pub segments: u32,
+    // 每段时长(秒)
+    pub segment_secs: f64,
+    // 宽度
+    pub width: u32,
+}
+
+impl TeaserConfig {
+    /// 从环境变量创建
+    /// `TEASER_FORMAT`: 输出格式webm|mp4, 默认webm
+    /// `TEASER_SEGMENTS`: 片段数, 默认8
+    /// `TEASER_SEGMENT_SECS`: 每段时长(秒), 默认1.5
+    /// `TEASER_WIDTH`: 宽度, 默认320
+    pub fn from_env() -> Self {
+        let format = var("TEASER_FORMAT")
+            .ok()
+            .map(|s| s.to_lowercase())
+            .filter(|s| matches!(s.as_str(), "webm" | "mp4"))
+            .unwrap_or_else(|| "webm".to_string());
+        let segments = var("TEASER_SEGMENTS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(8);
+        let segment_secs = var("TEASER_SEGMENT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0.0)
+            .unwrap_or(1.5);
+        let width = var("TEASER_WIDTH")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|w| *w > 0)
+            .unwrap_or(320);
+        Self {
+            format,
+            segments,
+            segment_secs,
+            width,
+        }
+    }
+
+    /// 生成参数, 用于判断是否需要重新生成
+    pub fn params(&self) -> String {
+        format!(
+            "format={};segments={};segment_secs={};width={}",
+            self.format, self.segments, self.segment_secs, self.width
+        )
+    }
+}
+
+/// 生成视频关键帧、gif、雪碧图、联系表和短视频预览片段, 输出到按hash_key分片的目录,
+/// 每种产物记录生成状态和参数, 已完成且参数未变化的产物跳过
+/// 每完成一个步骤通过report发出进度及生成的关键帧/预览, 返回关键帧数(done事件由任务队列在任务完成后发出)
+pub async fn generate_thumbnails(
+    pool: &SqlitePool,
+    hash_key: &str,
+    file_path: &str,
+    preview: PreviewMode,
+    report: &(dyn Fn(ThumbnailEvent) + Sync),
+) -> Result<u32> {
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    let file_info = dao::query_by_hash_key(pool, hash_key).await?;
+    let (media_info, streams) = match &file_info {
+        Some(fi) => (
+            dao::query_media_info(pool, fi.id).await?,
+            dao::query_media_streams(pool, fi.id).await?,
+        ),
+        None => (None, vec![]),
+    };
+    let duration = media_info.as_ref().and_then(|info| info.duration);
+    let codec = streams
+        .iter()
+        .find(|s| s.codec_type == "video")
+        .and_then(|s| s.codec_name.as_deref());
+    // 关键帧, 选取方式可按媒体库配置
+    let keyframe = KEYFRAME.for_file(file_path);
+    let plan = JobPlan::new(report, preview, duration, &keyframe);
+    let png_path = gen_out_png_path(&out_dir_path);
+    let params = keyframe.params();
+    let stage = plan.stage(ArtifactKind::Keyframes);
+    let keyframes_changed =
+        needs_generate(pool, hash_key, ArtifactKind::Keyframes, &png_path, &params).await?;
+    if keyframes_changed {
+        run_step(
+            pool,
+            hash_key,
+            ArtifactKind::Keyframes,
+            &png_path,
+            &params,
+            async |deadline| {
+                let pts_times = generate_atomically(&out_dir_path, gen_out_png_path, async |dir| {
+                    let png_path = gen_out_png_path(dir);
+                    generate_keyframes(
+                        file_path,
+                        &png_path,
+                        duration,
+                        codec,
+                        &keyframe,
+                        deadline,
+                        &|f| stage.update(f),
+                    )
+                    .await
+                })
+                .await?;
+                let keyframes = pts_times
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, pts_time)| Keyframe {
+                        hash_key: hash_key.to_string(),
+                        name: gen_keyframe_name(i),
+                        pts_time,
+                    })
+                    .collect::<Vec<_>>();
+                dao::replace_keyframes(pool, hash_key, &keyframes).await?;
+                Ok(keyframes.len() as u32)
+            },
+        )
+        .await?;
+    }
+    let keyframes = dao::query_keyframes(pool, hash_key).await?;
+    stage.update(1.0);
+    keyframe_events(hash_key, &keyframes)
+        .into_iter()
+        .for_each(report);
+    // 预览动图, 关键帧重新生成后也需要重新生成
+    let stage = plan.stage(ArtifactKind::Gif);
+    let gif_file = gen_out_gif_file(&out_dir_path);
+    let params = animation_params();
+    if !preview.includes_gif() {
+        info!("预览方式为{:?}, 跳过生成预览动图: {}", preview, file_path);
+    } else if keyframes.is_empty() {
+        warn!("没有关键帧, 跳过生成预览动图: {}", file_path);
+    } else if keyframes_changed
+        || needs_generate(pool, hash_key, ArtifactKind::Gif, &gif_file, &params).await?
+    {
+        run_step(
+            pool,
+            hash_key,
+            ArtifactKind::Gif,
+            &gif_file,
+            &params,
+            async |deadline| {
+                generate_atomically(&out_dir_path, gen_out_gif_path, async |dir| {
+                    generate_gif_by_keyframes(&png_path, dir, deadline).await
+                })
+                .await?;
+                Ok(keyframes.len() as u32)
+            },
+        )
+        .await?;
+    }
+    stage.update(1.0);
+    if preview.includes_gif()
+        && let Some(url) = gif_url(hash_key)
+    {
+        report(ThumbnailEvent::Gif { url, data: None });
+    }
+    // 雪碧图
+    let stage = plan.stage(ArtifactKind::Sprite);
+    let vtt_file = gen_out_sprite_vtt_file(&out_dir_path);
+    let params = sprite_params();
+    if keyframes.is_empty() {
+        warn!("没有关键帧时间点, 跳过生成雪碧图: {}", file_path);
+    } else if keyframes_changed
+        || needs_generate(pool, hash_key, ArtifactKind::Sprite, &vtt_file, &params).await?
+    {
+        run_step(
+            pool,
+            hash_key,
+            ArtifactKind::Sprite,
+            &vtt_file,
+            &params,
+            async |deadline| {
+                generate_atomically(&out_dir_path, gen_out_sprite_path, async |dir| {
+                    generate_sprites(&png_path, dir, &keyframes, duration, deadline).await
+                })
+                .await?;
+                Ok(keyframes.len() as u32)
+            },
+        )
+        .await?;
+    }
+    stage.update(1.0);
+    // 联系表
+    let stage = plan.stage(ArtifactKind::ContactSheet);
+    let config = &*CONTACT_SHEET;
+    let sheet_file = gen_out_contact_sheet_file(&out_dir_path, config);
+    let params = config.params();
+    match (&file_info, &media_info) {
+        (Some(fi), Some(mi)) if mi.duration.is_some() => {
+            if needs_generate(
+                pool,
+                hash_key,
+                ArtifactKind::ContactSheet,
+                &sheet_file,
+                &params,
+            )
+            .await?
+            {
+                // 联系表是可选产物, 失败(如ffmpeg缺少drawtext)时不影响其他产物
+                let result = run_step(
+                    pool,
+                    hash_key,
+                    ArtifactKind::ContactSheet,
+                    &sheet_file,
+                    &params,
+                    async |deadline| {
+                        generate_atomically(
+                            &out_dir_path,
+                            gen_out_contact_sheet_path,
+                            async |dir| {
+                                generate_contact_sheet(
+                                    dir,
+                                    fi,
+                                    mi,
+                                    &streams,
+                                    config,
+                                    deadline,
+                                    &|f| stage.update(f),
+                                )
+                                .await
+                            },
+                        )
+                        .await
+                    },
+                )
+                .await;
+                if let Err(e) = result {
+                    warn!("生成联系表失败, 跳过: {}: {}", file_path, e);
+                }
+            }
+        }
+        _ => warn!("没有视频时长信息, 跳过生成联系表: {}", file_path),
+    }
+    stage.update(1.0);
+    // 短视频预览片段
+    let stage = plan.stage(ArtifactKind::Teaser);
+    let config = &*TEASER;
+    let teaser_file = gen_out_teaser_file(&out_dir_path, config);
+    let params = config.params();
+    match duration {
+        _ if !preview.includes_clip() => {
+            info!("预览方式为{:?}, 跳过生成短视频预览: {}", preview, file_path)
+        }
+        Some(duration) => {
+            if needs_generate(pool, hash_key, ArtifactKind::Teaser, &teaser_file, &params).await? {
+                // 失败(如ffmpeg缺少编码器)时保留其他产物, 生成参数变化后才会重新生成
+                let result = run_step(
+                    pool,
+                    hash_key,
+                    ArtifactKind::Teaser,
+                    &teaser_file,
+                    &params,
+                    async |deadline| {
+                        generate_atomically(&out_dir_path, gen_out_teaser_path, async |dir| {
+                            generate_teaser(file_path, dir, duration, config, deadline, &|f| {
+                                stage.update(f)
+                            })
+                            .await
+                        })
+                        .await
+                    },
+                )
+                .await;
+                if let Err(e) = result {
+                    warn!("生成短视频预览失败, 跳过: {}: {}", file_path, e);
+                }
+            }
+        }
+        None => warn!("没有视频时长信息, 跳过生成短视频预览: {}", file_path),
+    }
+    stage.update(1.0);
+    if preview.includes_clip()
+        && let Some(url) = teaser_url(hash_key)
+    {
+        report(ThumbnailEvent::Clip { url });
+    }
+    Ok(keyframes.len() as u32)
+}
+
+/// 已生成的缩略图对应的事件: 关键帧、预览动图、短视频预览片段和done
+pub async fn current_events(pool: &SqlitePool, hash_key: &str) -> Result<Vec<ThumbnailEvent>> {
+    let keyframes = dao::query_keyframes(pool, hash_key).await?;
+    let mut events = keyframe_events(hash_key, &keyframes);
+    if let Some(url) = gif_url(hash_key) {
+        events.push(ThumbnailEvent::Gif { url, data: None });
+    }
+    if let Some(url) = teaser_url(hash_key) {
+        events.push(ThumbnailEvent::Clip { url });
+    }
+    events.push(ThumbnailEvent::Done {
+        frames: keyframes.len() as u32,
+    });
+    Ok(events)
+}
+
+/// 关键帧事件
+pub fn keyframe_events(hash_key: &str, keyframes: &[Keyframe]) -> Vec<ThumbnailEvent> {
+    let total = keyframes.len() as u32;
+    keyframes
+        .iter()
+        .enumerate()
+        .map(|(index, keyframe)| ThumbnailEvent::Frame {
+            index: index as u32,
+            total,
+            url: thumbnail_url(hash_key, &keyframe.name),
+            pts_time: keyframe.pts_time,
+            data: None,
+        })
+        .collect()
+}
+
+// 产物是否需要(重新)生成: 没有记录、未完成、失败、参数变化或文件已不存在
+async fn needs_generate(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+    path: &str,
+    params: &str,
+) -> Result<bool> {
+    let fresh = dao::query_artifact(pool, hash_key, kind)
+        .await?
+        .is_some_and(|artifact| is_fresh(&artifact, path, params));
+    if fresh {
+        info!("{:?}已生成, 跳过: {}", kind, path);
+    }
+    Ok(!fresh)
+}
+
+// 产物已按当前参数生成完成且文件存在, 帧数为0的记录(旧版本没有选取到关键帧时也记录为完成)需要重新生成
+fn is_fresh(artifact: &Artifact, path: &str, params: &str) -> bool {
+    artifact.state == ArtifactState::Done
+        && artifact.path == path
+        && artifact.params.as_deref() == Some(params)
+        && artifact.frame_count != Some(0)
+        && Path::new(path).exists()
+}
+
+// 执行一个生成步骤并记录状态, generate的参数为该步骤的截止时间, 返回帧数
+async fn run_step(
+    pool: &SqlitePool,
+    hash_key: &str,
+    kind: ArtifactKind,
+    path: &str,
+    params: &str,
+    generate: impl AsyncFnOnce(Instant) -> Result<u32>,
+) -> Result<()> {
+    dao::start_artifact(pool, hash_key, kind, path, params).await?;
+    let deadline = Instant::now() + process::TIMEOUT.stage(kind);
+    match generate(deadline).await {
+        Ok(frame_count) => dao::finish_artifact(pool, hash_key, kind, frame_count).await,
+        Err(e) => {
+            dao::fail_artifact(pool, hash_key, kind, &e.to_string()).await?;
+            Err(e)
+        }
+    }
+}
+
+// 任务的生成步骤及各步骤的预计耗时, 用于按耗时加权折算整体进度
+struct JobPlan<'a> {
+    report: &'a (dyn Fn(ThumbnailEvent) + Sync),
+    // 按执行顺序, 不需要生成的预览耗时为0
+    stages: Vec<(ArtifactKind, f64)>,
+}
+
+impl<'a> JobPlan<'a> {
+    fn new(
+        report: &'a (dyn Fn(ThumbnailEvent) + Sync),
+        preview: PreviewMode,
+        duration: Option<f64>,
+        keyframe: &KeyframeConfig,
+    ) -> Self {
+        let stages = [
+            ArtifactKind::Keyframes,
+            ArtifactKind::Gif,
+            ArtifactKind::Sprite,
+            ArtifactKind::ContactSheet,
+            ArtifactKind::Teaser,
+        ]
+        .into_iter()
+        .map(|kind| {
+            let wanted = match kind {
+                ArtifactKind::Gif => preview.includes_gif(),
+                ArtifactKind::Teaser => preview.includes_clip(),
+                _ => true,
+            };
+            let cost = if wanted {
+                stage_cost(kind, duration, keyframe)
+            } else {
+                0.0
+            };
+            (kind, cost)
+        })
+        .collect();
+        Self { report, stages }
+    }
+
+    fn stage(&self, stage: ArtifactKind) -> StageProgress<'a> {
+        let index = self
+            .stages
+            .iter()
+            .position(|(kind, _)| *kind == stage)
+            .unwrap_or(self.stages.len());
+        let cost_of = |stages: &[(ArtifactKind, f64)]| stages.iter().map(|(_, cost)| cost).sum();
+        StageProgress {
+            report: self.report,
+            stage,
+            before: cost_of(&self.stages[..index]),
+            cost: self.stages.get(index).map_or(0.0, |(_, cost)| *cost),
+            after: cost_of(self.stages.get(index + 1..).unwrap_or_default()),
+            total: cost_of(&self.stages),
+            start: Instant::now(),
+        }
+    }
+}
+
+// 步骤的预计耗时(相对值): 关键帧需要解码视频, 与时长成正比;
+// 其他步骤基于关键帧图片或固定数量的截取点, 与截取数量成正比
+fn stage_cost(kind: ArtifactKind, duration: Option<f64>, keyframe: &KeyframeConfig) -> f64 {
+    let duration = duration.unwrap_or(DEFAULT_DURATION_SECS);
+    match kind {
+        ArtifactKind::Keyframes => match keyframe.strategy {
+            // 解码所有帧
+            KeyframeStrategy::Scene => duration * 0.1,
+            // 只解码关键帧
+            KeyframeStrategy::Interval => duration * 0.02,
+            // 每个时间点一次seek
+            KeyframeStrategy::Even => keyframe.count as f64 * 0.5,
+        },
+        ArtifactKind::Gif | ArtifactKind::Sprite => 2.0,
+        ArtifactKind::ContactSheet => (CONTACT_SHEET.columns * CONTACT_SHEET.rows) as f64 * 0.5,
+        ArtifactKind::Teaser => TEASER.segments as f64 * 1.0,
+    }
+}
+
+// 单个生成步骤的进度, 按预计耗时折算为整体进度
+struct StageProgress<'a> {
+    report: &'a (dyn Fn(ThumbnailEvent) + Sync),
+    stage: ArtifactKind,
+    // 之前步骤、当前步骤、之后步骤和所有步骤的预计耗时
+    before: f64,
+    cost: f64,
+    after: f64,
+    total: f64,
+    start: Instant,
+}
+
+impl StageProgress<'_> {
+    // fraction: 当前步骤的完成比例(0~1)
+    fn update(&self, fraction: f64) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let percent = if self.total > 0.0 {
+            (self.before + self.cost * fraction) * 100.0 / self.total
+        } else {
+            100.0
+        };
+        let stage_eta_secs = (fraction > 0.0).then(|| elapsed * (1.0 - fraction) / fraction);
+        // 按当前步骤每单位预计耗时的实际用时估算剩余步骤, 步骤结束时(可能已跳过生成)无法估算
+        let eta_secs = if self.after == 0.0 {
+            stage_eta_secs
+        } else if fraction > 0.0 && fraction < 1.0 && self.cost > 0.0 {
+            let rate = elapsed / (self.cost * fraction);
+            Some(rate * (self.cost * (1.0 - fraction) + self.after))
+        } else {
+            None
+        };
+        (self.report)(ThumbnailEvent::Progress(JobProgress {
+            stage: self.stage,
+            percent,
+            stage_percent: fraction * 100.0,
+            stage_eta_secs,
+            eta_secs,
+        }));
+    }
+}
+
+// 在临时目录中生成产物, 成功后将产物目录移动到正式位置, 失败时删除临时目录,
+// 避免生成中断时正式目录中留下不完整的文件
+// target: 根据输出目录得到产物目录, 如gen_out_png_path; generate的参数为临时输出目录
+async fn generate_atomically<T>(
+    out_dir_path: &str,
+    target: fn(&str) -> String,
+    generate: impl AsyncFnOnce(&str) -> Result<T>,
+) -> Result<T> {
+    let dest = target(out_dir_path);
+    let name = Path::new(&dest)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_dir = format!("{}/{}{}", out_dir_path, TEMP_DIR_PREFIX, name);
+    // 同一文件的任务不会并发执行, 已存在的临时目录是上次中断遗留的
+    if Path::new(&temp_dir).exists() {
+        std::fs::remove_dir_all(&temp_dir)?;
+    }
+    std::fs::create_dir_all(&temp_dir)?;
+    let result = match generate(&temp_dir).await {
+        Ok(value) => replace_dir(&target(&temp_dir), &dest, &temp_dir).map(|_| value),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = std::fs::remove_dir_all(&temp_dir) {
+        warn!("删除临时目录失败 {}: {}", temp_dir, e);
+    }
+    result
+}
+
+// 用新目录替换正式目录: 旧目录先移到临时目录中(随临时目录一起删除), 再将新目录重命名,
+// 读取方只会看到旧目录、新目录或目录不存在, 不会看到写了一半的目录
+// 两次rename之间正式目录短暂不存在(rename不能覆盖非空目录), 此时请求其中的文件返回404;
+// 替换期间产物记录为生成中, 生成状态不是Ready, 客户端等待完成事件后再请求即可
+// 第二次rename失败时旧目录已移走, 产物记录为失败, 下次任务会重新生成
+fn replace_dir(from: &str, to: &str, temp_dir: &str) -> Result<()> {
+    if Path::new(to).exists() {
+        std::fs::rename(to, format!("{}/old", temp_dir))?;
+    }
+    std::fs::rename(from, to)?;
+    Ok(())
+}
+
+/// 清理上次异常退出时遗留的临时目录, 返回清理的数量
+pub fn sweep_temp_dirs() -> Result<usize> {
+    let output_path = Path::new(OUTPUT_DIR.as_str());
+    if !output_path.is_dir() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    // {output_path}/{分片1}/{分片2}/{hash_key}/{临时目录}
+    for shard1 in sub_dirs(output_path)? {
+        for shard2 in sub_dirs(&shard1)? {
+            for hash_dir in sub_dirs(&shard2)? {
+                for dir in sub_dirs(&hash_dir)? {
+                    let is_temp = dir
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with(TEMP_DIR_PREFIX));
+                    if !is_temp {
+                        continue;
+                    }
+                    match std::fs::remove_dir_all(&dir) {
+                        Ok(()) => removed += 1,
+                        Err(e) => warn!("删除临时目录失败 {}: {}", dir.display(), e),
+                    }
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+// 列出子目录
+fn sub_dirs(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+// 预览动图的生成参数
+fn animation_params() -> String {
+    format!(
+        "format={};quality={}",
+        ANIMATION.extension(),
+        KEYFRAME.quality
+    )
+}
+
+// 雪碧图的生成参数
+fn sprite_params() -> String {
+    format!(
+        "tile={}x{};grid={}x{}",
+        SPRITE_TILE_WIDTH, SPRITE_TILE_HEIGHT, SPRITE_COLUMNS, SPRITE_ROWS
+    )
+}
+
+/// 根据产物记录判断视频的缩略图生成状态: 关键帧按当前参数生成完成时为Partial,
+/// 预览方式需要的其他产物也都按当前参数生成完成时为Ready, 生成参数变化后需要重新生成
+/// 联系表和短视频预览是可选产物, 按当前参数生成失败时不再重新生成, 没有视频时长信息时不生成
+pub async fn thumbnail_status(
+    pool: &SqlitePool,
+    hash_key: &str,
+    preview: PreviewMode,
+) -> Result<ThumbnailStatus> {
+    let Some(file_info) = dao::query_by_hash_key(pool, hash_key).await? else {
+        return Ok(ThumbnailStatus::None);
+    };
+    let duration = dao::query_media_info(pool, file_info.id)
+        .await?
+        .and_then(|info| info.duration);
+    let artifacts = dao::query_artifacts(pool, hash_key).await?;
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    Ok(artifacts_status(
+        &artifacts,
+        &out_dir_path,
+        &file_info.file_path,
+        duration,
+        preview,
+    ))
+}
+
+// 根据产物记录和输出目录下的文件判断生成状态
+fn artifacts_status(
+    artifacts: &[Artifact],
+    out_dir_path: &str,
+    file_path: &str,
+    duration: Option<f64>,
+    preview: PreviewMode,
+) -> ThumbnailStatus {
+    let settled = |kind: ArtifactKind, path: &str, params: &str, optional: bool| {
+        artifacts
+            .iter()
+            .find(|artifact| artifact.kind == kind)
+            .is_some_and(|artifact| {
+                is_fresh(artifact, path, params)
+                    || (optional
+                        && artifact.state == ArtifactState::Failed
+                        && artifact.path == path
+                        && artifact.params.as_deref() == Some(params))
+            })
+    };
+    let keyframe = KEYFRAME.for_file(file_path);
+    if !settled(
+        ArtifactKind::Keyframes,
+        &gen_out_png_path(out_dir_path),
+        &keyframe.params(),
+        false,
+    ) {
+        return ThumbnailStatus::None;
+    }
+    let gif_ready = !preview.includes_gif()
+        || settled(
+            ArtifactKind::Gif,
+            &gen_out_gif_file(out_dir_path),
+            &animation_params(),
+            false,
+        );
+    let sprite_ready = settled(
+        ArtifactKind::Sprite,
+        &gen_out_sprite_vtt_file(out_dir_path),
+        &sprite_params(),
+        false,
+    );
+    let contact_sheet_ready = duration.is_none()
+        || settled(
+            ArtifactKind::ContactSheet,
+            &gen_out_contact_sheet_file(out_dir_path, &CONTACT_SHEET),
+            &CONTACT_SHEET.params(),
+            true,
+        );
+    let teaser_ready = duration.is_none()
+        || !preview.includes_clip()
+        || settled(
+            ArtifactKind::Teaser,
+            &gen_out_teaser_file(out_dir_path, &TEASER),
+            &TEASER.params(),
+            true,
+        );
+    if gif_ready && sprite_ready && contact_sheet_ready && teaser_ready {
+        ThumbnailStatus::Ready
+    } else {
+        ThumbnailStatus::Partial
     }
-    // 解析标准输出
-    let stdout = String::from_utf8(output.stdout)?;
-    // 转换为 u32
-    let num_frames = stdout.trim().parse::<u32>()?;
-    let out_dir_path = gen_file_dir_path(output_path, filename);
-    generate_keyframes(file_path, &out_dir_path).await?;
-    generate_gif_by_keyframes(&out_dir_path).await?;
-    Ok(num_frames)
 }
 
+/// 缩略图文件的访问url
+pub fn thumbnail_url(hash_key: &str, name: &str) -> String {
+    format!("/files/{}/thumbnails/{}", hash_key, name)
+}
+
+/// 联系表的访问url, 未生成时返回None
+pub fn contact_sheet_url(hash_key: &str) -> Option<String> {
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    let sheet_file = gen_out_contact_sheet_file(&out_dir_path, &CONTACT_SHEET);
+    let name = Path::new(&sheet_file)
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    Path::new(&sheet_file)
+        .is_file()
+        .then(|| thumbnail_url(hash_key, &name))
+}
+
+/// 预览动图的访问url, 未生成时返回None
+pub fn gif_url(hash_key: &str) -> Option<String> {
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    let gif_file = gen_out_gif_file(&out_dir_path);
+    let name = Path::new(&gif_file)
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    Path::new(&gif_file)
+        .is_file()
+        .then(|| thumbnail_url(hash_key, &name))
+}
+
+/// 短视频预览片段的访问url, 未生成时返回None
+pub fn teaser_url(hash_key: &str) -> Option<String> {
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    let teaser_file = gen_out_teaser_file(&out_dir_path, &TEASER);
+    let name = Path::new(&teaser_file)
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    Path::new(&teaser_file)
+        .is_file()
+        .then(|| thumbnail_url(hash_key, &name))
+}
+
+/// WebVTT缩略图轨道的访问url, 未生成时返回None
+pub fn thumbnail_track_url(hash_key: &str) -> Option<String> {
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    Path::new(&gen_out_sprite_vtt_file(&out_dir_path))
+        .is_file()
+        .then(|| thumbnail_url(hash_key, SPRITE_VTT_NAME))
+}
+
+/// 根据文件名查找缩略图文件(关键帧、gif、雪碧图、联系表或短视频预览), 文件名只允许字母数字和`._-`, 不允许路径
+pub fn find_thumbnail_file(hash_key: &str, name: &str) -> Option<PathBuf> {
+    let valid = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('.')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    };
+    if !valid(hash_key) || !valid(name) {
+        return None;
+    }
+    let out_dir_path = gen_hash_dir_path(OUTPUT_DIR.as_str(), hash_key);
+    [
+        gen_out_png_path(&out_dir_path),
+        gen_out_gif_path(&out_dir_path),
+        gen_out_sprite_path(&out_dir_path),
+        gen_out_contact_sheet_path(&out_dir_path),
+        gen_out_teaser_path(&out_dir_path),
+    ]
+    .into_iter()
+    .map(|dir| Path::new(&dir).join(name))
+    .find(|path| path.is_file())
+}
+
+/// 文件的输出目录: {output_path}/{分片1}/{分片2}/{hash_key}
+/// hash_key是十进制的128位哈希, 高位数字分布不均匀, 使用末尾4位作为分片
+pub fn gen_hash_dir_path(output_path: &str, hash_key: &str) -> String {
+    let len = hash_key.len();
+    let (shard1, shard2) = if len >= 4 {
+        (&hash_key[len - 2..], &hash_key[len - 4..len - 2])
+    } else {
+        ("00", "00")
+    };
+    format!("{}/{}/{}/{}", output_path, shard1, shard2, hash_key)
+}
+
+/// 旧版本按文件名(去除后缀)命名的输出目录, 仅用于迁移
 pub fn gen_file_dir_path(output_path: &str, filename: &String) -> String {
     format!("{}/{}", output_path, filename)
 }
 
-/// 生成视频关键帧
-pub async fn generate_keyframes(file_path: &str, out_dir_path: &str) -> Result<()> {
-    // ffmpeg -hwaccel cuda -skip_frame nokey -i ${file_path}  -fps_mode vfr -vf select='not(mod(n\,10))',blackframe=0,metadata=select:key=lavfi.blackframe.pblack:value=80:function=less,scale=320:-1:force_original_aspect_ratio=decrease -q:v 1 -y {}/%04d.png
-    let png_path = gen_out_png_path(out_dir_path);
-    // 不存在则创建
-    if !std::path::Path::new(&png_path).exists() {
-        std::fs::create_dir_all(&png_path)?;
+/// 将旧版本按文件名命名的输出目录迁移到按hash_key分片的目录
+pub async fn migrate_filename_dirs(pool: &SqlitePool) -> Result<()> {
+    let output_path = OUTPUT_DIR.as_str();
+    let mut moved = 0;
+    for fi in dao::query_all_file_info(pool).await? {
+        let old_dir = gen_file_dir_path(output_path, &FileInfo::obtain_filename(&fi.file_path));
+        let new_dir = gen_hash_dir_path(output_path, &fi.hash_key);
+        if !Path::new(&old_dir).is_dir() || Path::new(&new_dir).exists() {
+            continue;
+        }
+        if let Some(parent) = Path::new(&new_dir).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(e) = std::fs::rename(&old_dir, &new_dir) {
+            warn!("迁移输出目录失败 {} -> {}: {}", old_dir, new_dir, e);
+            continue;
+        }
+        let png_path = gen_out_png_path(&new_dir);
+        if Path::new(&png_path).is_dir() {
+            dao::upsert_artifact(pool, &fi.hash_key, ArtifactKind::Keyframes, &png_path).await?;
+        }
+        let gif_file = gen_out_animation_file(&new_dir, AnimationFormat::Gif);
+        if Path::new(&gif_file).is_file() {
+            dao::upsert_artifact(pool, &fi.hash_key, ArtifactKind::Gif, &gif_file).await?;
+        }
+        moved += 1;
+    }
+    info!("迁移输出目录: {}个", moved);
+    Ok(())
+}
+
+/// 生成视频关键帧到png_path目录, 返回每张图片(按顺序)在视频中的时间点, 通过on_progress报告进度(0~1),
+/// 超过deadline时结束ffmpeg并返回超时错误
+/// even方式需要视频时长, 时长未知时使用interval方式; interval/scene方式没有选取到任何帧(如全黑或没有场景变化)时改用even方式,
+/// 仍然没有时返回[NoKeyframesError]; codec为视频流的编码格式, 用于选择硬件解码
+pub async fn generate_keyframes(
+    file_path: &str,
+    png_path: &str,
+    duration: Option<f64>,
+    codec: Option<&str>,
+    config: &KeyframeConfig,
+    deadline: Instant,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<Vec<f64>> {
+    std::fs::create_dir_all(png_path)?;
+    let duration_secs = duration.filter(|d| *d > 0.0);
+    let mut config = config.clone();
+    if config.strategy == KeyframeStrategy::Even {
+        match duration_secs {
+            Some(duration) => {
+                return generate_even_keyframes(
+                    file_path,
+                    png_path,
+                    duration,
+                    &config,
+                    deadline,
+                    on_progress,
+                )
+                .await;
+            }
+            None => {
+                warn!(
+                    "没有视频时长信息, 使用interval方式选取关键帧: {}",
+                    file_path
+                );
+                config.strategy = KeyframeStrategy::Interval;
+            }
+        }
+    }
+    let pts_times = generate_selected_keyframes(
+        file_path,
+        png_path,
+        duration,
+        codec,
+        &config,
+        deadline,
+        on_progress,
+    )
+    .await?;
+    if !pts_times.is_empty() {
+        return Ok(pts_times);
+    }
+    let Some(duration) = duration_secs else {
+        return Err(NoKeyframesError.into());
+    };
+    warn!(
+        "{:?}方式没有选取到关键帧, 使用even方式: {}",
+        config.strategy, file_path
+    );
+    generate_even_keyframes(
+        file_path,
+        png_path,
+        duration,
+        &config,
+        deadline,
+        on_progress,
+    )
+    .await
+}
+
+/// 视频中没有选取到任何关键帧, 重试也不会改变结果
+#[derive(Error, Debug)]
+#[error("没有选取到任何关键帧")]
+pub struct NoKeyframesError;
+
+// interval/scene方式, 通过选帧滤镜一次ffmpeg调用输出所有关键帧
+async fn generate_selected_keyframes(
+    file_path: &str,
+    png_path: &str,
+    duration: Option<f64>,
+    codec: Option<&str>,
+    config: &KeyframeConfig,
+    deadline: Instant,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<Vec<f64>> {
+    // ffmpeg [-hwaccel cuda] -skip_frame nokey -i ${file_path}  -fps_mode vfr -vf select='not(mod(n\,10))',blackframe=0,metadata=select:key=lavfi.blackframe.pblack:value=80:function=less,scale='min(320,iw)':-1,showinfo [编码参数] -y {}/%04d.{png|jpg|webp|avif}
+    let hwaccel = hwaccel::resolve(codec).await;
+    let progress = || {
+        duration.map(|total_secs| Progress {
+            total_secs,
+            on_progress,
+        })
+    };
+    let output = process::output(
+        keyframes_command(file_path, png_path, hwaccel, config),
+        deadline,
+        progress(),
+    )
+    .await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        return Ok(parse_showinfo_pts(&stderr));
+    }
+    if hwaccel.is_none() {
+        return Err(anyhow::anyhow!("ffmpeg获取视频关键帧失败: {}", stderr));
+    }
+    // 硬件解码失败(如驱动不可用或编码格式不支持), 使用软件解码重试
+    warn!(
+        "硬件解码{:?}获取关键帧失败, 使用软件解码重试: {}",
+        hwaccel,
+        stderr.lines().last().unwrap_or_default()
+    );
+    let output = process::output(
+        keyframes_command(file_path, png_path, None, config),
+        deadline,
+        progress(),
+    )
+    .await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("ffmpeg获取视频关键帧失败: {}", stderr));
+    }
+    // 软件解码成功说明是硬件解码不支持该编码格式(而不是文件损坏), 之后该格式不再尝试硬件解码
+    if let Some(hwaccel) = hwaccel {
+        hwaccel::fall_back_to_software(hwaccel, codec);
     }
-    // 目录下png文件数大于20,则返回
-    let png_files = std::fs::read_dir(&png_path)?;
-    if png_files.count() > 20 {
-        info!("png目录下已有文件,跳过生成");
-        return Ok(());
+    Ok(parse_showinfo_pts(&stderr))
+}
+
+// 按视频时长均匀选取时间点, 每个时间点单独seek截取一帧
+async fn generate_even_keyframes(
+    file_path: &str,
+    png_path: &str,
+    duration: f64,
+    config: &KeyframeConfig,
+    deadline: Instant,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<Vec<f64>> {
+    // ffmpeg -ss ${time} -i ${file_path} -frames:v 1 -vf scale='min(320,iw)':-1 [编码参数] -y {}/0001.{ext}
+    let mut pts_times = vec![];
+    for i in 0..config.count {
+        // 时间点取每段的中间位置, 避开片头片尾
+        let time = duration * (i as f64 + 0.5) / config.count as f64;
+        let mut cmd = Command::new("ffmpeg");
+        cmd.arg("-ss")
+            .arg(format!("{:.3}", time))
+            .arg("-i")
+            .arg(file_path)
+            .arg("-frames:v")
+            .arg("1");
+        if let Some(scale) = config.scale_filter() {
+            cmd.arg("-vf").arg(scale);
+        }
+        cmd.args(config.format.encoder_args(config.quality))
+            .arg("-y")
+            .arg(format!(
+                "{}/{}",
+                png_path,
+                gen_keyframe_name(pts_times.len())
+            ));
+        let output = process::output(cmd, deadline, None).await?;
+        if output.status.success() {
+            pts_times.push(time);
+        } else {
+            warn!("截取第{}个关键帧失败({:.3}秒): {}", i + 1, time, file_path);
+        }
+        on_progress((i + 1) as f64 / config.count as f64);
+    }
+    if pts_times.is_empty() {
+        return Err(anyhow::anyhow!("ffmpeg获取视频关键帧失败: {}", file_path));
     }
+    Ok(pts_times)
+}
+
+/// 关键帧图片文件名, index从0开始, 与ffmpeg输出的%04d.{ext}(从1开始)对应
+pub fn gen_keyframe_name(index: usize) -> String {
+    format!("{:04}.{}", index + 1, KEYFRAME.format.extension())
+}
+
+/// 关键帧目录下已生成图片的扩展名, 配置修改前生成的图片可能与当前配置不同
+pub fn detect_keyframe_extension(png_path: &str) -> String {
+    std::fs::read_dir(png_path)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file())
+                .find_map(|path| Some(path.extension()?.to_string_lossy().to_string()))
+        })
+        .unwrap_or_else(|| KEYFRAME.format.extension().to_string())
+}
+
+// 从showinfo滤镜的日志中解析每个输出帧的时间点, 如:
+// [Parsed_showinfo_4 @ 0x5581] n:   0 pts:  12012 pts_time:0.5005  duration:...
+// 每个输出帧都对应一个图片文件, 时间点缺失(NOPTS)或无法解析时沿用上一帧的时间点, 保证与文件一一对应
+fn parse_showinfo_pts(stderr: &str) -> Vec<f64> {
+    let mut pts_times = vec![];
+    for line in stderr
+        .lines()
+        .filter(|line| line.contains("Parsed_showinfo"))
+    {
+        // 颜色、side data等附加信息行没有pts_time
+        let Some(value) = line.split("pts_time:").nth(1) else {
+            continue;
+        };
+        let pts_time = value
+            .split_whitespace()
+            .next()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|t| t.is_finite())
+            .map(|t| t.max(0.0))
+            .unwrap_or_else(|| pts_times.last().copied().unwrap_or(0.0));
+        pts_times.push(pts_time);
+    }
+    pts_times
+}
+
+// 生成关键帧的ffmpeg命令, hwaccel为None时使用软件解码
+fn keyframes_command(
+    file_path: &str,
+    png_path: &str,
+    hwaccel: Option<HwAccel>,
+    config: &KeyframeConfig,
+) -> Command {
     let mut cmd = Command::new("ffmpeg");
-    cmd.arg("-hwaccel")
-        .arg("cuda")
-        .arg("-skip_frame")
-        .arg("nokey")
-        .arg("-i")
+    cmd.arg("-progress").arg("pipe:1");
+    if let Some(hw) = hwaccel.and_then(|hw| hw.as_ffmpeg_arg()) {
+        cmd.arg("-hwaccel").arg(hw);
+    }
+    // interval方式只解码关键帧, scene方式需要解码所有帧计算场景变化
+    if config.strategy != KeyframeStrategy::Scene {
+        cmd.arg("-skip_frame").arg("nokey");
+    }
+    cmd.arg("-i")
         .arg(file_path)
         .arg("-fps_mode")
         .arg("vfr")
-        .arg("-vf")
-        // 取每10帧的关键帧
-        .arg("select='not(mod(n\\,10))',blackframe=0,metadata=select:key=lavfi.blackframe.pblack:value=70:function=less,scale=320:-1:force_original_aspect_ratio=decrease")
-        .arg("-q:v")
-        .arg("1")
+        .arg("-vf");
+    // 选取关键帧并过滤黑帧, showinfo输出每张图片的时间点
+    let mut filters = vec![
+        config.select_filter(),
+        "blackframe=0".to_string(),
+        "metadata=select:key=lavfi.blackframe.pblack:value=70:function=less".to_string(),
+    ];
+    filters.extend(config.scale_filter());
+    filters.push("showinfo".to_string());
+    cmd.arg(filters.join(","))
+        .args(config.format.encoder_args(config.quality))
         .arg("-y")
-        .arg(format!("{}/%04d.png", png_path));
-    let output = cmd.output().await?;
-    if !output.status.success() {
-        let stderr = String::from_utf8(output.stderr)?;
-        return Err(anyhow::anyhow!("ffmpeg获取视频关键帧失败: {}", stderr));
-    }
-    Ok(())
+        .arg(format!("{}/%04d.{}", png_path, config.format.extension()));
+    cmd
 }
 
+/// 关键帧目录, 历史原因命名为png, 图片格式由配置决定
 pub fn gen_out_png_path(out_dir_path: &str) -> String {
     let png_path = format!("{}/png", out_dir_path);
     png_path
@@ -97,30 +1475,740 @@ pub fn gen_out_gif_path(out_dir_path: &str) -> String {
     gif_path
 }
 
-/// 生成gif
-pub async fn generate_gif_by_keyframes(output_dir_path: &str) -> Result<()> {
-    // ffmpeg -i ${png_path}/%04d.png -vf scale=320:-1:flags=lanczos,fps=10 -c:v gif -loop 0 -y ${out_path}/gif/${filename}.gif
+/// 预览动图文件, 格式由配置决定
+pub fn gen_out_gif_file(out_dir_path: &str) -> String {
+    gen_out_animation_file(out_dir_path, *ANIMATION)
+}
+
+pub fn gen_out_animation_file(out_dir_path: &str, format: AnimationFormat) -> String {
+    format!(
+        "{}/0.{}",
+        gen_out_gif_path(out_dir_path),
+        format.extension()
+    )
+}
+
+pub fn gen_out_sprite_path(out_dir_path: &str) -> String {
+    format!("{}/sprite", out_dir_path)
+}
+
+pub fn gen_out_sprite_vtt_file(out_dir_path: &str) -> String {
+    format!("{}/{}", gen_out_sprite_path(out_dir_path), SPRITE_VTT_NAME)
+}
+
+pub fn gen_out_contact_sheet_path(out_dir_path: &str) -> String {
+    format!("{}/contact", out_dir_path)
+}
+
+pub fn gen_out_contact_sheet_file(out_dir_path: &str, config: &ContactSheetConfig) -> String {
+    format!(
+        "{}/sheet.{}",
+        gen_out_contact_sheet_path(out_dir_path),
+        config.format
+    )
+}
+
+pub fn gen_out_teaser_path(out_dir_path: &str) -> String {
+    format!("{}/clip", out_dir_path)
+}
+
+pub fn gen_out_teaser_file(out_dir_path: &str, config: &TeaserConfig) -> String {
+    format!(
+        "{}/teaser.{}",
+        gen_out_teaser_path(out_dir_path),
+        config.format
+    )
+}
+
+// 雪碧图文件名, index从0开始, 与ffmpeg输出的sprite-%03d.jpg(从1开始)对应
+fn gen_sprite_name(index: usize) -> String {
+    format!("sprite-{:03}.jpg", index + 1)
+}
+
+/// 根据png_path目录的关键帧生成预览动图(gif或动态webp), 输出到output_dir_path下的gif目录
+pub async fn generate_gif_by_keyframes(
+    png_path: &str,
+    output_dir_path: &str,
+    deadline: Instant,
+) -> Result<()> {
+    // ffmpeg -i ${png_path}/%04d.{ext} -vf scale=320:-1:flags=lanczos,fps=3 -c:v gif -loop 0 -y ${out_path}/gif/0.gif
     let gif_path = gen_out_gif_path(output_dir_path);
     // 不存在则创建
-    if !std::path::Path::new(&gif_path).exists() {
+    if !Path::new(&gif_path).exists() {
         info!("创建gif目录: {}", gif_path);
-        std::fs::create_dir_all(&gif_path).expect("创建gif目录失败");
+        std::fs::create_dir_all(&gif_path)?;
     }
+    let ext = detect_keyframe_extension(png_path);
     let mut cmd = Command::new("ffmpeg");
     cmd.arg("-i")
-        .arg(format!("{}/png/%04d.png", output_dir_path))
+        .arg(format!("{}/%04d.{}", png_path, ext))
         .arg("-vf")
-        .arg("scale=320:-1:flags=lanczos,fps=3")
-        .arg("-c:v")
-        .arg("gif")
-        .arg("-loop")
+        .arg("scale=320:-1:flags=lanczos,fps=3");
+    match *ANIMATION {
+        AnimationFormat::Gif => cmd.arg("-c:v").arg("gif"),
+        AnimationFormat::Webp => cmd
+            .arg("-c:v")
+            .arg("libwebp_anim")
+            .arg("-quality")
+            .arg(KEYFRAME.quality.min(100).to_string()),
+    };
+    cmd.arg("-loop")
         .arg("0")
         .arg("-y")
-        .arg(format!("{}/0.gif", gif_path));
-    let output = cmd.output().await?;
+        .arg(gen_out_gif_file(output_dir_path));
+    let output = process::output(cmd, deadline, None).await?;
     if !output.status.success() {
         let stderr = String::from_utf8(output.stderr)?;
-        return Err(anyhow::anyhow!("ffmpeg生成gif失败: {}", stderr));
+        return Err(anyhow::anyhow!("ffmpeg生成预览动图失败: {}", stderr));
+    }
+    Ok(())
+}
+
+/// 将png_path目录的关键帧拼接为雪碧图, 并生成WebVTT缩略图轨道(`#xywh=`指定每个时间段对应的区域),
+/// 输出到output_dir_path下的sprite目录
+pub async fn generate_sprites(
+    png_path: &str,
+    output_dir_path: &str,
+    keyframes: &[Keyframe],
+    duration: Option<f64>,
+    deadline: Instant,
+) -> Result<()> {
+    // ffmpeg -i ${png_path}/%04d.{ext} -vf scale=160:90:force_original_aspect_ratio=decrease,pad=160:90:(ow-iw)/2:(oh-ih)/2,tile=10x10 -q:v 3 -y ${out_path}/sprite/sprite-%03d.jpg
+    let sprite_path = gen_out_sprite_path(output_dir_path);
+    if !Path::new(&sprite_path).exists() {
+        std::fs::create_dir_all(&sprite_path)?;
+    }
+    let (w, h) = (SPRITE_TILE_WIDTH, SPRITE_TILE_HEIGHT);
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-i")
+        .arg(format!("{}/%04d.{}", png_path, detect_keyframe_extension(png_path)))
+        .arg("-vf")
+        // 缩放后居中填充为固定大小, 保证每张缩略图在雪碧图中的位置可计算
+        .arg(format!(
+            "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,tile={}x{}",
+            SPRITE_COLUMNS, SPRITE_ROWS
+        ))
+        .arg("-fps_mode")
+        .arg("passthrough")
+        .arg("-q:v")
+        .arg("3")
+        .arg("-y")
+        .arg(format!("{}/sprite-%03d.jpg", sprite_path));
+    let output = process::output(cmd, deadline, None).await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("ffmpeg生成雪碧图失败: {}", stderr));
     }
+    let vtt = gen_sprite_vtt(keyframes, duration);
+    tokio::fs::write(gen_out_sprite_vtt_file(output_dir_path), vtt).await?;
     Ok(())
 }
+
+// 生成WebVTT内容, 每个关键帧对应从其时间点到下一关键帧时间点的区间
+fn gen_sprite_vtt(keyframes: &[Keyframe], duration: Option<f64>) -> String {
+    let per_sprite = (SPRITE_COLUMNS * SPRITE_ROWS) as usize;
+    let mut vtt = String::from("WEBVTT\n");
+    for (i, keyframe) in keyframes.iter().enumerate() {
+        let start = keyframe.pts_time;
+        let end = match keyframes.get(i + 1) {
+            Some(next) => next.pts_time,
+            // 最后一帧到视频结束, 时长未知时取10秒
+            None => duration.filter(|d| *d > start).unwrap_or(start + 10.0),
+        };
+        let pos = (i % per_sprite) as u32;
+        let x = pos % SPRITE_COLUMNS * SPRITE_TILE_WIDTH;
+        let y = pos / SPRITE_COLUMNS * SPRITE_TILE_HEIGHT;
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            format_vtt_time(start),
+            format_vtt_time(end),
+            gen_sprite_name(i / per_sprite),
+            x,
+            y,
+            SPRITE_TILE_WIDTH,
+            SPRITE_TILE_HEIGHT
+        ));
+    }
+    vtt
+}
+
+// WebVTT时间格式: HH:MM:SS.mmm
+fn format_vtt_time(secs: f64) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// 生成联系表: 从视频中均匀截取 列数×行数 帧拼接为一张图, 每帧标注时间点,
+/// 顶部标注文件名、大小、时长、分辨率和编码, 返回截取成功的帧数
+pub async fn generate_contact_sheet(
+    output_dir_path: &str,
+    file_info: &FileInfo,
+    media_info: &MediaInfo,
+    streams: &[MediaStream],
+    config: &ContactSheetConfig,
+    deadline: Instant,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<u32> {
+    let duration = media_info
+        .duration
+        .filter(|d| *d > 0.0)
+        .ok_or_else(|| anyhow::anyhow!("缺少视频时长"))?;
+    let sheet_path = gen_out_contact_sheet_path(output_dir_path);
+    let tiles_path = format!("{}/tiles", sheet_path);
+    std::fs::create_dir_all(&tiles_path)?;
+    let font = config
+        .font_file
+        .as_ref()
+        .map(|f| format!(":fontfile='{}'", escape_filter_value(f)))
+        .unwrap_or_default();
+    // 截取每一帧, 时间点取每段的中间位置, 避开片头片尾
+    let count = config.columns * config.rows;
+    let mut extracted = 0;
+    for i in 0..count {
+        let time = duration * (i as f64 + 0.5) / count as f64;
+        // ffmpeg -ss ${time} -i ${file_path} -frames:v 1 -vf scale=320:-2,drawtext=text='00\:01\:23':x=w-tw-6:y=h-th-6:fontcolor=white:box=1:boxcolor=black@0.6 -y ${tiles_path}/%03d.png
+        let mut cmd = Command::new("ffmpeg");
+        cmd.arg("-ss")
+            .arg(format!("{:.3}", time))
+            .arg("-i")
+            .arg(&file_info.file_path)
+            .arg("-frames:v")
+            .arg("1")
+            .arg("-vf")
+            .arg(format!(
+                "scale={}:-2,drawtext=text='{}':x=w-tw-6:y=h-th-6:fontsize=16:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=3:expansion=none{}",
+                config.tile_width,
+                escape_filter_value(&format_duration(time)),
+                font
+            ))
+            .arg("-y")
+            .arg(format!("{}/{:03}.png", tiles_path, extracted + 1));
+        let output = process::output(cmd, deadline, None).await?;
+        if output.status.success() {
+            extracted += 1;
+        } else {
+            warn!(
+                "截取联系表第{}帧失败({:.3}秒): {}",
+                i + 1,
+                time,
+                file_info.file_path
+            );
+        }
+        // 最后拼接所需时间很短, 截取完成计为90%
+        on_progress((i + 1) as f64 / count as f64 * 0.9);
+    }
+    if extracted == 0 {
+        std::fs::remove_dir_all(&tiles_path).ok();
+        return Err(anyhow::anyhow!(
+            "ffmpeg截取联系表帧失败: {}",
+            file_info.file_path
+        ));
+    }
+    // 顶部信息写入文件, 避免转义
+    let header_file = format!("{}/header.txt", tiles_path);
+    std::fs::write(
+        &header_file,
+        gen_contact_sheet_header(file_info, media_info, streams),
+    )?;
+    // ffmpeg -i ${tiles_path}/%03d.png -vf tile=4x4:padding=4:margin=4,pad=iw:ih+80:0:80,drawtext=textfile='...' -frames:v 1 -y ${sheet_path}/sheet.jpg
+    let header_height = 80;
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-i")
+        .arg(format!("{}/%03d.png", tiles_path))
+        .arg("-vf")
+        .arg(format!(
+            "tile={}x{}:padding=4:margin=4,pad=iw:ih+{h}:0:{h},drawtext=textfile='{}':x=10:y=10:fontsize=18:line_spacing=6:fontcolor=white:expansion=none{}",
+            config.columns,
+            config.rows,
+            escape_filter_value(&header_file),
+            font,
+            h = header_height
+        ))
+        .arg("-frames:v")
+        .arg("1")
+        .arg("-q:v")
+        .arg("3")
+        .arg("-y")
+        .arg(gen_out_contact_sheet_file(output_dir_path, config));
+    let output = process::output(cmd, deadline, None).await?;
+    std::fs::remove_dir_all(&tiles_path).ok();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("ffmpeg生成联系表失败: {}", stderr));
+    }
+    Ok(extracted)
+}
+
+/// 生成短视频预览片段: 从视频中均匀截取若干段, 缩放后拼接为无声的webm/mp4, 返回片段数
+pub async fn generate_teaser(
+    file_path: &str,
+    output_dir_path: &str,
+    duration: f64,
+    config: &TeaserConfig,
+    deadline: Instant,
+    on_progress: &(dyn Fn(f64) + Sync),
+) -> Result<u32> {
+    // ffmpeg -ss ${t1} -t 1.5 -i ${file_path} -ss ${t2} -t 1.5 -i ${file_path} ... -filter_complex [0:v]fps=24,scale=320:-2,setsar=1[v0];...;[v0][v1]...concat=n=8:v=1:a=0[out] -map [out] -an ${out_path}/clip/teaser.webm
+    let teaser_path = gen_out_teaser_path(output_dir_path);
+    if !Path::new(&teaser_path).exists() {
+        std::fs::create_dir_all(&teaser_path)?;
+    }
+    // 视频过短时减少片段数, 避免片段重叠
+    let segments = config
+        .segments
+        .min((duration / config.segment_secs).floor() as u32)
+        .max(1);
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg("-progress").arg("pipe:1");
+    let mut filters = vec![];
+    let mut concat_inputs = String::new();
+    for i in 0..segments {
+        // 每段取所在区间的中间位置
+        let start =
+            (duration * (i as f64 + 0.5) / segments as f64 - config.segment_secs / 2.0).max(0.0);
+        cmd.arg("-ss")
+            .arg(format!("{:.3}", start))
+            .arg("-t")
+            .arg(format!("{:.3}", config.segment_secs))
+            .arg("-i")
+            .arg(file_path);
+        filters.push(format!(
+            "[{i}:v]fps=24,scale={}:-2,setsar=1[v{i}]",
+            config.width
+        ));
+        concat_inputs.push_str(&format!("[v{i}]"));
+    }
+    filters.push(format!(
+        "{}concat=n={}:v=1:a=0[out]",
+        concat_inputs, segments
+    ));
+    cmd.arg("-filter_complex")
+        .arg(filters.join(";"))
+        .arg("-map")
+        .arg("[out]")
+        .arg("-an");
+    if config.format == "mp4" {
+        cmd.arg("-c:v")
+            .arg("libx264")
+            .arg("-preset")
+            .arg("veryfast")
+            .arg("-crf")
+            .arg("28")
+            .arg("-pix_fmt")
+            .arg("yuv420p")
+            // moov放在文件开头, 便于浏览器边下边播
+            .arg("-movflags")
+            .arg("+faststart");
+    } else {
+        cmd.arg("-c:v")
+            .arg("libvpx-vp9")
+            .arg("-b:v")
+            .arg("0")
+            .arg("-crf")
+            .arg("40")
+            .arg("-deadline")
+            .arg("realtime")
+            .arg("-row-mt")
+            .arg("1");
+    }
+    cmd.arg("-y")
+        .arg(gen_out_teaser_file(output_dir_path, config));
+    let total_secs = segments as f64 * config.segment_secs;
+    let progress = Progress {
+        total_secs,
+        on_progress,
+    };
+    let output = process::output(cmd, deadline, Some(progress)).await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("ffmpeg生成短视频预览失败: {}", stderr));
+    }
+    Ok(segments)
+}
+
+// 联系表顶部信息, 使用英文标签避免默认字体不支持中文
+fn gen_contact_sheet_header(
+    file_info: &FileInfo,
+    media_info: &MediaInfo,
+    streams: &[MediaStream],
+) -> String {
+    let filename = Path::new(&file_info.file_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let video = streams.iter().find(|s| s.codec_type == "video");
+    let audio = streams.iter().find(|s| s.codec_type == "audio");
+    let resolution = video
+        .and_then(|v| Some(format!("{}x{}", v.width?, v.height?)))
+        .unwrap_or_else(|| "-".to_string());
+    let codec = |s: Option<&MediaStream>| {
+        s.and_then(|s| s.codec_name.clone())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    format!(
+        "File: {}\nSize: {}    Duration: {}\nResolution: {}    Video: {}    Audio: {}",
+        filename,
+        format_size(file_info.file_size),
+        format_duration(media_info.duration.unwrap_or_default()),
+        resolution,
+        codec(video),
+        codec(audio)
+    )
+}
+
+// 时长格式: HH:MM:SS
+fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// 文件大小格式, 如: 1.23 GB
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", size, UNITS[unit])
+}
+
+// 转义ffmpeg滤镜参数值(用于单引号内): 路径统一为/, 转义冒号, 去除单引号
+fn escape_filter_value(value: &str) -> String {
+    value
+        .replace('\\', "/")
+        .replace(':', "\\:")
+        .replace('\'', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_showinfo_pts() {
+        let cases: [(&str, Vec<f64>); 7] = [
+            ("", vec![]),
+            (
+                "[Parsed_showinfo_4 @ 0x5581] n:   0 pts:  12012 pts_time:0.5005  duration:1001\n\
+                 [Parsed_showinfo_4 @ 0x5581] n:   1 pts: 240240 pts_time:10.01   duration:1001",
+                vec![0.5005, 10.01],
+            ),
+            // 其他滤镜和附加信息行
+            (
+                "[Parsed_blackframe_1 @ 0x1] frame:3 pblack:99 pts:3 t:0.120000\n\
+                 [Parsed_showinfo_4 @ 0x5581] n:   0 pts:      0 pts_time:0\n\
+                 [Parsed_showinfo_4 @ 0x5581]   color_range:tv color_space:bt709\n\
+                 frame=    1 fps=0.0 q=-0.0 size=N/A time=00:00:00.00",
+                vec![0.0],
+            ),
+            // 时间点缺失沿用上一帧
+            (
+                "[Parsed_showinfo_4 @ 0x5581] n:   0 pts:    500 pts_time:5\n\
+                 [Parsed_showinfo_4 @ 0x5581] n:   1 pts:NOPTS pts_time:NOPTS\n\
+                 [Parsed_showinfo_4 @ 0x5581] n:   2 pts:    900 pts_time:9",
+                vec![5.0, 5.0, 9.0],
+            ),
+            // 行尾截断
+            (
+                "[Parsed_showinfo_4 @ 0x5581] n:   0 pts: 0 pts_time:",
+                vec![0.0],
+            ),
+            (
+                "[Parsed_showinfo_4 @ 0x5581] n:   0 pts: 0 pts_time:nan",
+                vec![0.0],
+            ),
+            // 负时间点按0处理
+            (
+                "[Parsed_showinfo_4 @ 0x5581] n:   0 pts: -2 pts_time:-0.08",
+                vec![0.0],
+            ),
+        ];
+        for (stderr, expected) in cases {
+            assert_eq!(parse_showinfo_pts(stderr), expected, "{:?}", stderr);
+        }
+    }
+
+    #[test]
+    fn test_format_vtt_time() {
+        let cases = [
+            (0.0, "00:00:00.000"),
+            (-1.5, "00:00:00.000"),
+            (0.0016, "00:00:00.002"),
+            (59.9994, "00:00:59.999"),
+            (59.9996, "00:01:00.000"),
+            (3599.999, "00:59:59.999"),
+            (3599.9996, "01:00:00.000"),
+            (3600.0, "01:00:00.000"),
+            (3661.25, "01:01:01.250"),
+            (36000.0, "10:00:00.000"),
+            (360000.0, "100:00:00.000"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(format_vtt_time(secs), expected, "format_vtt_time({})", secs);
+        }
+    }
+
+    #[test]
+    fn test_gen_sprite_vtt() {
+        let keyframe = |i: usize, pts_time: f64| Keyframe {
+            hash_key: "hash".to_string(),
+            name: gen_keyframe_name(i),
+            pts_time,
+        };
+        let cases = [
+            (vec![], Some(100.0), "WEBVTT\n".to_string()),
+            (
+                vec![keyframe(0, 3590.0), keyframe(1, 3600.0)],
+                Some(3630.5),
+                "WEBVTT\n\
+                 \n00:59:50.000 --> 01:00:00.000\nsprite-001.jpg#xywh=0,0,160,90\n\
+                 \n01:00:00.000 --> 01:00:30.500\nsprite-001.jpg#xywh=160,0,160,90\n"
+                    .to_string(),
+            ),
+            // 时长未知或不大于最后一帧时, 最后一帧取10秒
+            (
+                vec![keyframe(0, 3595.0)],
+                None,
+                "WEBVTT\n\n00:59:55.000 --> 01:00:05.000\nsprite-001.jpg#xywh=0,0,160,90\n"
+                    .to_string(),
+            ),
+            (
+                vec![keyframe(0, 3595.0)],
+                Some(3595.0),
+                "WEBVTT\n\n00:59:55.000 --> 01:00:05.000\nsprite-001.jpg#xywh=0,0,160,90\n"
+                    .to_string(),
+            ),
+        ];
+        for (keyframes, duration, expected) in cases {
+            assert_eq!(
+                gen_sprite_vtt(&keyframes, duration),
+                expected,
+                "{:?}",
+                keyframes
+            );
+        }
+    }
+
+    #[test]
+    fn test_gen_sprite_vtt_tiles() {
+        let per_sprite = (SPRITE_COLUMNS * SPRITE_ROWS) as usize;
+        let keyframes = (0..per_sprite + 1)
+            .map(|i| Keyframe {
+                hash_key: "hash".to_string(),
+                name: gen_keyframe_name(i),
+                pts_time: i as f64 * 60.0,
+            })
+            .collect::<Vec<_>>();
+        let vtt = gen_sprite_vtt(&keyframes, None);
+        let cues = vtt
+            .lines()
+            .filter(|line| line.contains("#xywh="))
+            .collect::<Vec<_>>();
+        let cases = [
+            (0, "sprite-001.jpg#xywh=0,0,160,90"),
+            (
+                SPRITE_COLUMNS as usize - 1,
+                "sprite-001.jpg#xywh=1440,0,160,90",
+            ),
+            (SPRITE_COLUMNS as usize, "sprite-001.jpg#xywh=0,90,160,90"),
+            (per_sprite - 1, "sprite-001.jpg#xywh=1440,810,160,90"),
+            (per_sprite, "sprite-002.jpg#xywh=0,0,160,90"),
+        ];
+        assert_eq!(cues.len(), keyframes.len());
+        for (index, expected) in cases {
+            assert_eq!(cues[index], expected, "cue {}", index);
+        }
+        // 第60帧开始于1小时
+        assert!(vtt.contains("\n01:00:00.000 --> 01:01:00.000\nsprite-001.jpg#xywh=0,540,160,90\n"));
+    }
+
+    #[test]
+    fn test_keyframe_config_for_file() {
+        let value = std::env::join_paths([
+            "/media/anime=scene",
+            "/media/anime/movie = Even",
+            "/media/tv=unknown",
+            "=interval",
+            "",
+        ])
+        .unwrap();
+        let mut config = KeyframeConfig::from_env();
+        config.strategy = KeyframeStrategy::Interval;
+        config.library_strategies = parse_library_strategies(&value.to_string_lossy());
+        assert_eq!(config.library_strategies.len(), 2);
+        let cases = [
+            ("/media/anime/a.mp4", KeyframeStrategy::Scene),
+            ("/media/anime/movie/b.mp4", KeyframeStrategy::Even),
+            // 按目录匹配, 不按字符串前缀
+            ("/media/anime2/c.mp4", KeyframeStrategy::Interval),
+            ("/media/tv/d.mp4", KeyframeStrategy::Interval),
+        ];
+        for (file_path, expected) in cases {
+            assert_eq!(
+                config.for_file(file_path).strategy,
+                expected,
+                "{}",
+                file_path
+            );
+        }
+        assert_eq!(
+            config.for_file("/media/anime/a.mp4").params(),
+            config.for_file("/media/anime/e.mkv").params()
+        );
+        assert_ne!(
+            config.for_file("/media/anime/a.mp4").params(),
+            config.for_file("/media/tv/d.mp4").params()
+        );
+    }
+
+    #[test]
+    fn test_artifacts_status() {
+        let out_dir = std::env::temp_dir().join(format!("videoinfo-status-{}", std::process::id()));
+        let out_dir_path = out_dir.to_string_lossy().replace('\\', "/");
+        let file_path = "/media/a.mp4";
+        let current = [
+            (
+                ArtifactKind::Keyframes,
+                gen_out_png_path(&out_dir_path),
+                KEYFRAME.for_file(file_path).params(),
+            ),
+            (
+                ArtifactKind::Gif,
+                gen_out_gif_file(&out_dir_path),
+                animation_params(),
+            ),
+            (
+                ArtifactKind::Sprite,
+                gen_out_sprite_vtt_file(&out_dir_path),
+                sprite_params(),
+            ),
+            (
+                ArtifactKind::ContactSheet,
+                gen_out_contact_sheet_file(&out_dir_path, &CONTACT_SHEET),
+                CONTACT_SHEET.params(),
+            ),
+            (
+                ArtifactKind::Teaser,
+                gen_out_teaser_file(&out_dir_path, &TEASER),
+                TEASER.params(),
+            ),
+        ];
+        for (kind, path, _) in &current {
+            if *kind == ArtifactKind::Keyframes {
+                std::fs::create_dir_all(path).unwrap();
+            } else {
+                std::fs::create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+                std::fs::write(path, b"").unwrap();
+            }
+        }
+        let done = |kinds: &[ArtifactKind]| {
+            current
+                .iter()
+                .filter(|(kind, _, _)| kinds.contains(kind))
+                .map(|(kind, path, params)| Artifact {
+                    id: 0,
+                    hash_key: "hash".to_string(),
+                    kind: *kind,
+                    path: path.clone(),
+                    created_at: 0,
+                    state: ArtifactState::Done,
+                    params: Some(params.clone()),
+                    frame_count: Some(1),
+                    error: None,
+                    completed_at: Some(0),
+                })
+                .collect::<Vec<_>>()
+        };
+        let all = [
+            ArtifactKind::Keyframes,
+            ArtifactKind::Gif,
+            ArtifactKind::Sprite,
+            ArtifactKind::ContactSheet,
+            ArtifactKind::Teaser,
+        ];
+        let status = |artifacts: &[Artifact], duration: Option<f64>, preview: PreviewMode| {
+            artifacts_status(artifacts, &out_dir_path, file_path, duration, preview)
+        };
+        let duration = Some(60.0);
+        assert_eq!(
+            status(&[], duration, PreviewMode::Gif),
+            ThumbnailStatus::None
+        );
+        assert_eq!(
+            status(&done(&all), duration, PreviewMode::Both),
+            ThumbnailStatus::Ready
+        );
+        // 预览方式不需要的产物
+        let without_teaser = done(&all[..4]);
+        assert_eq!(
+            status(&without_teaser, duration, PreviewMode::Gif),
+            ThumbnailStatus::Ready
+        );
+        assert_eq!(
+            status(&without_teaser, duration, PreviewMode::Clip),
+            ThumbnailStatus::Partial
+        );
+        // 没有时长信息时不生成联系表和短视频预览
+        assert_eq!(
+            status(&done(&all[..3]), None, PreviewMode::Both),
+            ThumbnailStatus::Ready
+        );
+        assert_eq!(
+            status(&done(&all[..3]), duration, PreviewMode::Gif),
+            ThumbnailStatus::Partial
+        );
+        // 迁移前生成的关键帧没有参数, 以及参数变化后都需要重新生成
+        let mut artifacts = done(&all);
+        artifacts[0].params = None;
+        assert_eq!(
+            status(&artifacts, duration, PreviewMode::Gif),
+            ThumbnailStatus::None
+        );
+        let mut artifacts = done(&all);
+        artifacts[1].params = Some("format=gif;quality=0".to_string());
+        assert_eq!(
+            status(&artifacts, duration, PreviewMode::Gif),
+            ThumbnailStatus::Partial
+        );
+        // 没有选取到关键帧的记录
+        let mut artifacts = done(&all);
+        artifacts[0].frame_count = Some(0);
+        assert_eq!(
+            status(&artifacts, duration, PreviewMode::Gif),
+            ThumbnailStatus::None
+        );
+        // 可选产物按当前参数生成失败, 必需产物生成失败
+        let mut artifacts = done(&all);
+        artifacts[3].state = ArtifactState::Failed;
+        artifacts[4].state = ArtifactState::Failed;
+        assert_eq!(
+            status(&artifacts, duration, PreviewMode::Both),
+            ThumbnailStatus::Ready
+        );
+        artifacts[3].params = None;
+        assert_eq!(
+            status(&artifacts, duration, PreviewMode::Both),
+            ThumbnailStatus::Partial
+        );
+        let mut artifacts = done(&all);
+        artifacts[2].state = ArtifactState::Failed;
+        assert_eq!(
+            status(&artifacts, duration, PreviewMode::Gif),
+            ThumbnailStatus::Partial
+        );
+        // 文件已删除
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        assert_eq!(
+            status(&done(&all), duration, PreviewMode::Both),
+            ThumbnailStatus::None
+        );
+    }
+}